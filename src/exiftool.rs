@@ -1,6 +1,16 @@
-use std::path::Path;
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+    sync::Mutex,
+};
 
-use crate::files::Timestamp;
+use anyhow::Context;
+
+use crate::{
+    files::{DateTag, Meta, TimestampSource},
+    timestamp::{self, Timestamp},
+};
 
 #[derive(serde::Deserialize, Debug)]
 struct Fields {
@@ -56,6 +66,16 @@ struct Fields {
     )]
     track_create_date: Option<Timestamp>,
 
+    #[serde(rename = "OffsetTimeOriginal", default)]
+    offset_time_original: Option<String>,
+
+    /// Either a string or, if it looks like one, a number.
+    #[serde(rename = "SubSecTimeOriginal", default)]
+    sub_sec_time_original: Option<serde_json::Value>,
+
+    #[serde(rename = "Model", default)]
+    model: Option<String>,
+
     #[serde(
         rename = "FileModifyDate",
         deserialize_with = "exiftool_parse_date",
@@ -75,34 +95,42 @@ where
 
     match Option::deserialize(deserializer)? {
         None => Ok(None),
-        Some(data) => naive_date_time_parse(data)
+        Some(data) => date_time_parse(data)
             .map(Some)
             .map_err(serde::de::Error::custom),
     }
 }
 
-fn naive_date_time_parse(
-    data: &str,
-) -> chrono::format::ParseResult<Timestamp> {
-    const FMT: &str = "%Y:%m:%d %H:%M:%S";
-    let mut data = data.to_owned();
-    // Drop timezone if it is present:
-    if let Some(pos) = data.find(['+', '-']) {
-        data.truncate(pos);
-    }
-    // Drop subseconds if they're present:
-    if let Some(pos) = data.find(['.']) {
-        data.truncate(pos);
-    }
-    chrono::NaiveDateTime::parse_from_str(&data, FMT)
+fn date_time_parse(data: &str) -> chrono::format::ParseResult<Timestamp> {
+    const FMT: &str = "%Y:%m:%d %H:%M:%S%.f";
+    // Split-off timezone if it is present:
+    let (data, offset) = match data.find(['+', '-', 'Z']) {
+        Some(pos) if pos > 0 => {
+            (&data[..pos], timestamp::parse_offset(&data[pos..]).ok())
+        }
+        Some(_) | None => (data, None),
+    };
+    chrono::NaiveDateTime::parse_from_str(data, FMT)
+        .map(|local| Timestamp::new(local, offset))
 }
 
 #[tracing::instrument(level = "error", skip_all)]
-pub fn read_timestamp(path: &Path) -> Option<Timestamp> {
+fn read_meta(pool: &Pool, path: &Path, tags: &[DateTag]) -> Option<Meta> {
     let path = path.as_os_str().to_string_lossy().to_string();
-    let out = cmd("exiftool", &["-json", &path])?;
+    // QuickTimeUTC makes exiftool convert QuickTime dates, which are in UTC
+    // only, to its zone, which is UTC as well (see Process::spawn), so that
+    // they come with the +00:00 offset, by which we tell them apart.
+    let out = pool.execute(&["-json", "-api", "QuickTimeUTC", &path])?;
     tracing::debug!(out = ?String::from_utf8_lossy(&out[..]), "Output raw");
-    let parse_result = serde_json::from_slice::<Vec<Fields>>(&out[..]);
+    parse_meta(&out, tags)
+}
+
+/// Meta from the JSON output of exiftool, with the timestamp from the first
+/// of the tags found.
+fn parse_meta(out: &[u8], tags: &[DateTag]) -> Option<Meta> {
+    use chrono::Timelike; // Set subseconds.
+
+    let parse_result = serde_json::from_slice::<Vec<Fields>>(out);
     tracing::debug!(?parse_result, "Output parsed");
     let mut fields_vec = parse_result.ok()?;
     if fields_vec.len() > 1 {
@@ -120,30 +148,209 @@ pub fn read_timestamp(path: &Path) -> Option<Timestamp> {
         date_time_created,
         date_time_original,
         track_create_date,
+        offset_time_original,
+        sub_sec_time_original,
+        model,
         _file_modify_date,
     } = fields_vec.pop()?;
-    date_time_original
-        .or(date_time_created)
-        .or(creation_date)
-        .or(create_date)
-        .or(date_created)
-        .or(date_create)
-        .or(track_create_date)
+    let date_time_original = date_time_original.map(|t| {
+        let offset = t.offset.or_else(|| {
+            offset_time_original
+                .as_deref()
+                .and_then(|offset| timestamp::parse_offset(offset).ok())
+        });
+        let local = sub_sec_time_original
+            .as_ref()
+            .filter(|_| t.local.nanosecond() == 0)
+            .and_then(|subsec| match subsec {
+                serde_json::Value::String(s) => timestamp::parse_subsec(s),
+                other => timestamp::parse_subsec(&other.to_string()),
+            })
+            .and_then(|nanos| t.local.with_nanosecond(nanos))
+            .unwrap_or(t.local);
+        Timestamp::new(local, offset)
+    });
+    // QuickTime's, unlike those of EXIF, which carry no offset.
+    let utc_only = |t: Option<Timestamp>| match t {
+        Some(t) if t.offset.is_some_and(|o| o.local_minus_utc() == 0) => {
+            Some(Timestamp::from_utc(t.local))
+        }
+        t => t,
+    };
+    let create_date = utc_only(create_date);
+    let track_create_date = utc_only(track_create_date);
+    let found = tags.iter().find_map(|tag| {
+        let t = match tag {
+            DateTag::DateTimeOriginal => date_time_original,
+            DateTag::DateTimeCreated => date_time_created,
+            DateTag::CreationDate => creation_date,
+            DateTag::CreateDate => create_date,
+            DateTag::DateCreated => date_created,
+            DateTag::Datecreate => date_create,
+            DateTag::TrackCreateDate => track_create_date,
+        }?;
+        Some((t, *tag))
+    });
+    Some(Meta {
+        timestamp: found.map(|(t, _)| (t, TimestampSource::Exiftool)),
+        tag: found.map(|(_, tag)| tag),
+        model,
+    })
 }
 
-fn cmd(exe: &str, args: &[&str]) -> Option<Vec<u8>> {
-    let out = std::process::Command::new(exe).args(args).output().ok()?;
-    if out.status.success() {
-        Some(out.stdout)
-    } else {
-        tracing::error!(
-            ?exe,
-            ?args,
-            ?out,
-            stderr = ?String::from_utf8_lossy(&out.stderr[..]),
-            "Failed to execute command."
-        );
-        None
+/// Long-lived exiftool processes, each reading arguments from its stdin
+/// (`-stay_open True -@ -`), so that we don't pay for starting one (i.e.
+/// Perl) per file. Processes are started on demand, so there are at most as
+/// many as there are threads needing them at once.
+pub struct Pool {
+    exe: PathBuf,
+    args: Vec<String>,
+    idle: Mutex<Vec<Process>>,
+}
+
+impl Pool {
+    /// The given args are passed along with those of every command.
+    pub fn new(exe: &Path, args: &[String]) -> Self {
+        Self {
+            exe: exe.to_path_buf(),
+            args: args.to_vec(),
+            idle: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Check that exiftool can be run and get its version, which must look
+    /// like one, e.g. `12.76`, lest any program exiting with 0 pass.
+    pub fn probe(&self) -> anyhow::Result<String> {
+        let out = Command::new(&self.exe)
+            .arg("-ver")
+            .stdin(Stdio::null())
+            .output()
+            .with_context(|| format!("Failed to execute {:?}", self.exe))?;
+        if !out.status.success() {
+            anyhow::bail!(
+                "{:?} -ver failed with {}: {}",
+                self.exe,
+                out.status,
+                String::from_utf8_lossy(&out.stderr).trim()
+            );
+        }
+        let version = String::from_utf8_lossy(&out.stdout).trim().to_string();
+        if version.parse::<f64>().is_err() {
+            anyhow::bail!(
+                "{:?} -ver printed no version, but: {:?}",
+                self.exe,
+                version
+            );
+        }
+        Ok(version)
+    }
+
+    pub fn read_meta(&self, path: &Path, tags: &[DateTag]) -> Option<Meta> {
+        read_meta(self, path, tags)
+    }
+
+    fn execute(&self, args: &[&str]) -> Option<Vec<u8>> {
+        let idle = self.idle.lock().ok()?.pop();
+        let mut process = match idle {
+            Some(process) => process,
+            None => {
+                let exe = &self.exe;
+                Process::spawn(exe)
+                    .map_err(|error| {
+                        tracing::error!(?exe, ?error, "Failed to spawn.");
+                    })
+                    .ok()?
+            }
+        };
+        let args: Vec<&str> = self
+            .args
+            .iter()
+            .map(String::as_str)
+            .chain(args.iter().copied())
+            .collect();
+        match process.execute(&args) {
+            Ok(out) => {
+                self.idle.lock().ok()?.push(process);
+                Some(out)
+            }
+            Err(error) => {
+                // Discard the process, since it is in an unknown state.
+                let exe = &self.exe;
+                tracing::error!(?exe, ?args, ?error, "Failed to execute.");
+                None
+            }
+        }
+    }
+}
+
+struct Process {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl Process {
+    /// Marks the end of the output of each command.
+    const READY: &'static str = "{ready}";
+
+    fn spawn(exe: &Path) -> io::Result<Self> {
+        let mut child = Command::new(exe)
+            .args(["-stay_open", "True", "-@", "-"])
+            // The zone QuickTimeUTC converts to.
+            .env("TZ", "UTC")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdin = child.stdin.take().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::BrokenPipe, "No stdin")
+        })?;
+        let stdout = child.stdout.take().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::BrokenPipe, "No stdout")
+        })?;
+        Ok(Self {
+            child,
+            stdin,
+            stdout: BufReader::new(stdout),
+        })
+    }
+
+    /// Run a command, given one argument per line, and collect its output.
+    fn execute(&mut self, args: &[&str]) -> io::Result<Vec<u8>> {
+        if let Some(arg) = args.iter().find(|arg| arg.contains('\n')) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Newline in argument: {:?}", arg),
+            ));
+        }
+        for arg in args {
+            writeln!(self.stdin, "{}", arg)?;
+        }
+        writeln!(self.stdin, "-execute")?;
+        self.stdin.flush()?;
+        let mut out = Vec::new();
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            if self.stdout.read_until(b'\n', &mut line)? == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            if line.trim_ascii_end() == Self::READY.as_bytes() {
+                return Ok(out);
+            }
+            out.extend_from_slice(&line);
+        }
+    }
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        let stopped = writeln!(self.stdin, "-stay_open\nFalse")
+            .and_then(|()| self.stdin.flush());
+        if stopped.is_err() {
+            let _ = self.child.kill();
+        }
+        let _ = self.child.wait();
     }
 }
 
@@ -151,38 +358,131 @@ fn cmd(exe: &str, args: &[&str]) -> Option<Vec<u8>> {
 mod tests {
     use super::*;
 
+    #[cfg(unix)]
     #[test]
-    fn t_naive_date_time_parse() {
-        assert!(naive_date_time_parse("").is_err());
-        assert!(naive_date_time_parse("-200:88:90 1:2:3").is_err());
+    fn t_pool() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // Stand-in for exiftool, speaking enough of its -stay_open protocol.
+        let dir = tempfile::tempdir().unwrap();
+        let exe = dir.path().join("exiftool");
+        std::fs::write(
+            &exe,
+            r#"#!/bin/sh
+if [ "$1" = -ver ]; then
+    echo 12.76
+    exit 0
+fi
+while read -r arg; do
+    case "$arg" in
+        -execute)
+            echo '[{"SourceFile": "x", "DateTimeOriginal": "2022:10:03 17:52:16"}]'
+            echo '{ready}'
+            ;;
+        False)
+            exit 0
+            ;;
+    esac
+done
+"#,
+        )
+        .unwrap();
+        std::fs::set_permissions(
+            &exe,
+            std::fs::Permissions::from_mode(0o755),
+        )
+        .unwrap();
+
+        let args = ["-api".to_string(), "largefilesupport=1".to_string()];
+        let pool = Pool::new(&exe, &args);
+        assert_eq!("12.76", pool.probe().unwrap());
+        for _ in 0..3 {
+            let meta = pool
+                .read_meta(Path::new("foo.jpg"), crate::files::DATE_TAGS)
+                .unwrap();
+            assert_eq!(
+                Some("2022-10-03T17:52:16".to_string()),
+                meta.timestamp.map(|(t, _)| t.to_string())
+            );
+        }
+        assert_eq!(1, pool.idle.lock().unwrap().len());
+
+        let pool = Pool::new(&dir.path().join("nope"), &[]);
+        assert!(pool.probe().is_err());
+
+        // Exits with 0, but is not exiftool.
+        for script in ["#!/bin/sh\n", "#!/bin/sh\necho usage: foo\n"] {
+            let exe = dir.path().join("impostor");
+            std::fs::write(&exe, script).unwrap();
+            std::fs::set_permissions(
+                &exe,
+                std::fs::Permissions::from_mode(0o755),
+            )
+            .unwrap();
+            assert!(Pool::new(&exe, &[]).probe().is_err());
+        }
+        assert!(pool
+            .read_meta(Path::new("foo.jpg"), crate::files::DATE_TAGS)
+            .is_none());
+    }
+
+    #[test]
+    fn t_date_time_parse() {
+        let date = chrono::NaiveDate::from_ymd_opt(2022, 10, 3).unwrap();
+        let local = chrono::NaiveDateTime::new(
+            date,
+            chrono::NaiveTime::from_hms_opt(17, 52, 16).unwrap(),
+        );
+        let local_subsec = chrono::NaiveDateTime::new(
+            date,
+            chrono::NaiveTime::from_hms_nano_opt(17, 52, 16, 597_752_928)
+                .unwrap(),
+        );
+        let offset = |s: &str| Some(timestamp::parse_offset(s).unwrap());
+        assert!(date_time_parse("").is_err());
+        assert!(date_time_parse("-200:88:90 1:2:3").is_err());
         assert_eq!(
-            chrono::NaiveDateTime::new(
-                chrono::NaiveDate::from_ymd_opt(2022, 10, 3).unwrap(),
-                chrono::NaiveTime::from_hms_opt(17, 52, 16).unwrap(),
-            ),
-            naive_date_time_parse("2022:10:03 17:52:16.597752928733826Z")
-                .unwrap()
+            Timestamp::new(local_subsec, offset("Z")),
+            date_time_parse("2022:10:03 17:52:16.597752928733826Z").unwrap()
         );
         assert_eq!(
-            chrono::NaiveDateTime::new(
-                chrono::NaiveDate::from_ymd_opt(2022, 10, 3).unwrap(),
-                chrono::NaiveTime::from_hms_opt(17, 52, 16).unwrap(),
-            ),
-            naive_date_time_parse("2022:10:03 17:52:16").unwrap()
+            Timestamp::new(local, None),
+            date_time_parse("2022:10:03 17:52:16").unwrap()
         );
         assert_eq!(
-            chrono::NaiveDateTime::new(
-                chrono::NaiveDate::from_ymd_opt(2022, 10, 3).unwrap(),
-                chrono::NaiveTime::from_hms_opt(17, 52, 16).unwrap(),
-            ),
-            naive_date_time_parse("2022:10:03 17:52:16+4:00").unwrap()
+            Timestamp::new(local, offset("+04:00")),
+            date_time_parse("2022:10:03 17:52:16+4:00").unwrap()
+        );
+        assert_eq!(
+            Timestamp::new(local, offset("-07:00")),
+            date_time_parse("2022:10:03 17:52:16-7:00").unwrap()
+        );
+    }
+
+    #[test]
+    fn t_parse_meta() {
+        use DateTag::*;
+
+        let out = br#"[{
+            "SourceFile": "foo.mov",
+            "CreateDate": "2022:10:03 17:52:16",
+            "TrackCreateDate": "2021:01:02 03:04:05",
+            "Model": "Foo"
+        }]"#;
+        let found = |tags: &[DateTag]| {
+            let meta = parse_meta(out, tags).unwrap();
+            assert_eq!(Some("Foo"), meta.model.as_deref());
+            meta.tag.zip(meta.timestamp.map(|(t, _)| t.to_string()))
+        };
+        assert_eq!(
+            Some((CreateDate, "2022-10-03T17:52:16".to_string())),
+            found(&[DateTimeOriginal, CreateDate, TrackCreateDate])
         );
         assert_eq!(
-            chrono::NaiveDateTime::new(
-                chrono::NaiveDate::from_ymd_opt(2022, 10, 3).unwrap(),
-                chrono::NaiveTime::from_hms_opt(17, 52, 16).unwrap(),
-            ),
-            naive_date_time_parse("2022:10:03 17:52:16-7:00").unwrap()
+            Some((TrackCreateDate, "2021-01-02T03:04:05".to_string())),
+            found(&[DateTimeOriginal, TrackCreateDate, CreateDate])
         );
+        assert_eq!(None, found(&[DateTimeOriginal, Datecreate]));
+        assert!(parse_meta(b"nope", crate::files::DATE_TAGS).is_none());
     }
 }
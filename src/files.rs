@@ -1,17 +1,27 @@
 use std::{
-    collections::VecDeque,
-    ffi::OsStr,
-    fs,
-    path::{Path, PathBuf},
+    collections::{HashMap, VecDeque},
+    fs, io, ops,
+    path::{self, Path, PathBuf},
 };
 
 use anyhow::Context;
 use rayon::prelude::*;
 
-use crate::{exiftool, hash::Hash};
+use crate::{
+    exiftool,
+    hash::{Hash, Hasher, Reading},
+    manifest,
+    source::Source,
+    template::{self, Template},
+    time_shift::TimeShifts,
+    timestamp::{self, AssumedZone, Timestamp, Zone},
+};
 
 // TODO Keep clap/CLI-specific stuff out of lib code.
-#[derive(clap::Subcommand, Debug)]
+#[derive(
+    clap::Subcommand, Debug, Clone, Copy, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
 pub enum Op {
     /// Dry run. Just print what would be done.
     Show,
@@ -21,45 +31,239 @@ pub enum Op {
 
     /// Move into the directory structure in dst (i.e. remove the original files from src).
     Move,
+
+    /// Hardlink into the directory structure in dst (i.e. preserve the
+    /// original files in src, without duplicating their data). src and dst
+    /// must be on the same filesystem.
+    Link,
+
+    /// Reflink (copy-on-write clone) into the directory structure in dst,
+    /// falling back to copy if the filesystem does not support it.
+    Reflink,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    clap::ValueEnum,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
 pub enum Typ {
     Img,
     Vid,
 }
 
-pub type Timestamp = chrono::NaiveDateTime;
+/// Where a timestamp was found.
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimestampSource {
+    /// Metadata read by us directly.
+    Native,
+
+    /// Metadata read by exiftool.
+    Exiftool,
+
+    /// Date and time found in the file name.
+    Filename,
+
+    /// File creation time, as reported by the filesystem.
+    Birthtime,
+
+    /// File modification time.
+    Mtime,
+}
+
+/// Metadata tags a timestamp may be read from, named as by exiftool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum DateTag {
+    #[value(name = "DateTimeOriginal")]
+    DateTimeOriginal,
+
+    #[value(name = "DateTimeCreated")]
+    DateTimeCreated,
+
+    #[value(name = "CreationDate")]
+    CreationDate,
+
+    /// A.k.a. DateTimeDigitized in Exif.
+    #[value(name = "CreateDate")]
+    CreateDate,
+
+    #[value(name = "DateCreated")]
+    DateCreated,
+
+    #[value(name = "Datecreate")]
+    Datecreate,
+
+    #[value(name = "TrackCreateDate")]
+    TrackCreateDate,
+}
+
+/// Default timestamp tag priority.
+pub const DATE_TAGS: &[DateTag] = &[
+    DateTag::DateTimeOriginal,
+    DateTag::DateTimeCreated,
+    DateTag::CreationDate,
+    DateTag::CreateDate,
+    DateTag::DateCreated,
+    DateTag::Datecreate,
+    DateTag::TrackCreateDate,
+];
+
+/// Tags read natively from EXIF, in images and in videos carrying it.
+const EXIF_DATE_TAGS: &[DateTag] =
+    &[DateTag::DateTimeOriginal, DateTag::CreateDate];
+
+/// Tags read natively from QuickTime tracks. nom-exif reads one of them,
+/// without telling us which, so it stands for all.
+const TRACK_DATE_TAGS: &[DateTag] = &[
+    DateTag::CreationDate,
+    DateTag::CreateDate,
+    DateTag::TrackCreateDate,
+];
+
+/// Metadata needed to determine a file's destination.
+#[derive(Debug, Default)]
+pub struct Meta {
+    pub timestamp: Option<(Timestamp, TimestampSource)>,
+
+    /// Tag the timestamp was read from, if any.
+    pub tag: Option<DateTag>,
+
+    pub model: Option<String>,
+}
+
+impl Meta {
+    fn or(self, other: Self) -> Self {
+        let (timestamp, tag) = match self.timestamp {
+            Some(_) => (self.timestamp, self.tag),
+            None => (other.timestamp, other.tag),
+        };
+        Self {
+            timestamp,
+            tag,
+            model: self.model.or(other.model),
+        }
+    }
+
+    /// Position of the timestamp's tag in the priority, or past its end if
+    /// there is none.
+    fn rank(&self, tags: &[DateTag]) -> usize {
+        self.tag
+            .and_then(|tag| tags.iter().position(|t| *t == tag))
+            .unwrap_or(tags.len())
+    }
+}
+
+/// What happened to a file which we attempted to organize.
+#[derive(
+    Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Outcome {
+    /// Written to dst.
+    Organized,
+
+    /// dst already existed, or was src itself.
+    Present,
+
+    /// Content already existed elsewhere in dst.
+    PresentElsewhere,
+
+    Failed,
+
+    /// Reversed by undo.
+    Undone,
+
+    /// Renamed by rehash, under which it is recorded again.
+    Rehashed,
+}
 
 #[derive(Debug)]
-struct File {
+struct File<'a, H: Hasher> {
     src: PathBuf,
     dst: PathBuf,
+    typ: Typ,
+    timestamp: Option<(Timestamp, TimestampSource)>,
+    hash: &'a H,
+    reading: Reading,
+    digest: String,
 }
 
-impl File {
+impl<'a, H: Hasher> File<'a, H> {
     fn new(
         root: &Path,
         src: &Path,
         typ: Typ,
-        img_dir: &str,
-        vid_dir: &str,
-        ts: Timestamp,
-        hash: Hash,
+        (timestamp, timestamp_source): (Timestamp, TimestampSource),
+        model: Option<&str>,
         digest: &str,
+        opts: &'a Options<H>,
     ) -> Self {
         Self {
             src: src.to_path_buf(),
-            dst: dst(
-                root,
-                src,
-                typ,
-                img_dir,
-                vid_dir,
-                ts,
-                hash.name(),
-                digest,
-            ),
+            dst: dst(root, src, typ, timestamp, model, digest, opts),
+            typ,
+            timestamp: Some((timestamp, timestamp_source)),
+            hash: &opts.hash,
+            reading: opts.reading,
+            digest: digest.to_string(),
+        }
+    }
+
+    /// A file without a timestamp, destined for the unsorted directory,
+    /// named by its digest, followed by its original name.
+    fn unsorted(
+        src: &Path,
+        typ: Typ,
+        digest: &str,
+        unsorted_dir: &str,
+        opts: &'a Options<H>,
+    ) -> Self {
+        let tag =
+            template::hash_tag(opts.hash.name(), digest, opts.portable_names);
+        let original = src.file_name().unwrap_or_default().to_string_lossy();
+        let original = if opts.portable_names {
+            template::sanitize(&original, true)
+        } else {
+            original.to_string()
+        };
+        let name = [tag, original].join("--");
+        Self {
+            src: src.to_path_buf(),
+            dst: PathBuf::from(unsorted_dir).join(name),
+            typ,
+            timestamp: None,
+            hash: &opts.hash,
+            reading: opts.reading,
+            digest: digest.to_string(),
+        }
+    }
+
+    fn manifest_entry(
+        &self,
+        run_id: &str,
+        dst: &Path,
+        op: Op,
+        result: &anyhow::Result<Outcome>,
+    ) -> manifest::Entry {
+        manifest::Entry {
+            run_id: run_id.to_string(),
+            time: chrono::Local::now().to_rfc3339(),
+            src: self.src.clone(),
+            dst: dst.to_path_buf(),
+            typ: self.typ,
+            timestamp: self.timestamp.map(|(timestamp, _)| timestamp),
+            timestamp_source: self.timestamp.map(|(_, source)| source),
+            hash: self.hash.name().to_string(),
+            digest: self.digest.clone(),
+            op,
+            outcome: *result.as_ref().unwrap_or(&Outcome::Failed),
+            error: result.as_ref().err().map(|error| format!("{:#}", error)),
         }
     }
 
@@ -71,9 +275,10 @@ impl File {
     fn organize(
         &self,
         dst_root: &Path,
-        permanently: bool,
+        op: &Op,
         force: bool,
-    ) -> anyhow::Result<()> {
+        verify: bool,
+    ) -> anyhow::Result<Outcome> {
         tracing::info!("Organizing");
         let src = self.src.as_path();
         let dst = dst_root.join(&self.dst);
@@ -87,30 +292,260 @@ impl File {
         if exists && src == dst {
             // XXX src should already be canonicalized.
             tracing::warn!(?src, ?dst, "Skipping. Identical src and dst.");
-            return Ok(());
+            return Ok(Outcome::Present);
         }
         if exists && !force {
             tracing::warn!(
                 ?dst,
                 "Skipping. dst exists, but force overwrite not requested."
             );
-            return Ok(());
+            return Ok(Outcome::Present);
         }
-        if permanently {
-            tracing::info!("Moving");
-            fs::rename(src, &dst).context(format!(
-                "Failed to rename file. src={:?}. dst={:?}",
-                src, &dst
-            ))?;
+        match op {
+            Op::Show => unreachable!("Show op does not organize files."),
+            Op::Copy => {
+                tracing::info!("Copying");
+                self.write(&dst, verify, |tmp| {
+                    fs::copy(src, tmp).map(|_| ())
+                })
+                .context(format!(
+                    "Failed to copy file. src={:?}. dst={:?}",
+                    src, &dst
+                ))?;
+            }
+            Op::Move => {
+                tracing::info!("Moving");
+                move_file(src, &dst, self.hash, self.reading, &self.digest)?;
+            }
+            Op::Link => {
+                tracing::info!("Linking");
+                write_atomically(&dst, |tmp| {
+                    // Linking refuses to replace the placeholder.
+                    fs::remove_file(tmp)?;
+                    fs::hard_link(src, tmp)
+                })
+                .context(format!(
+                    "Failed to hardlink file. src={:?}. dst={:?}",
+                    src, &dst
+                ))?;
+            }
+            Op::Reflink => {
+                tracing::info!("Reflinking");
+                // Verifiable, since reflink may fall back to copy.
+                self.write(&dst, verify, |tmp| {
+                    // Reflinking refuses to replace the placeholder.
+                    fs::remove_file(tmp)?;
+                    reflink_copy::reflink_or_copy(src, tmp).map(|_| ())
+                })
+                .context(format!(
+                    "Failed to reflink file. src={:?}. dst={:?}",
+                    src, &dst
+                ))?;
+            }
+        }
+        Ok(Outcome::Organized)
+    }
+
+    fn write<W>(
+        &self,
+        dst: &Path,
+        verify: bool,
+        write: W,
+    ) -> anyhow::Result<()>
+    where
+        W: FnOnce(&Path) -> io::Result<()>,
+    {
+        if verify {
+            write_atomically_checked(dst, write, |tmp| {
+                tracing::info!("Verifying");
+                self.check_digest(tmp)
+            })
         } else {
-            tracing::info!("Copying");
-            fs::copy(src, &dst).context(format!(
-                "Failed to copy file. src={:?}. dst={:?}",
-                src, &dst
-            ))?;
+            write_atomically(dst, write)
+        }
+    }
+
+    fn check_digest(&self, path: &Path) -> anyhow::Result<()> {
+        check_digest(path, self.hash, self.reading, &self.digest)
+            .context(format!("Failed to verify copy of src: {:?}", self.src))
+    }
+}
+
+/// Rename, falling back to copy+verify+delete when src and dst are on
+/// different devices.
+pub fn move_file<H: Hasher>(
+    src: &Path,
+    dst: &Path,
+    hash: &H,
+    reading: Reading,
+    digest: &str,
+) -> anyhow::Result<()> {
+    match fs::rename(src, dst) {
+        Ok(()) => Ok(()),
+        Err(error) if error.kind() == io::ErrorKind::CrossesDevices => {
+            tracing::info!("Cross-device. Falling back to copy and delete.");
+            move_across_devices(src, dst, hash, reading, digest)
         }
-        Ok(())
+        Err(error) => Err(error).context(format!(
+            "Failed to rename file. src={:?}. dst={:?}",
+            src, dst
+        )),
+    }
+}
+
+/// Copy, verify, then delete src. src is only removed once the dst is
+/// fully written and its digest matches the one computed from src.
+fn move_across_devices<H: Hasher>(
+    src: &Path,
+    dst: &Path,
+    hash: &H,
+    reading: Reading,
+    digest: &str,
+) -> anyhow::Result<()> {
+    write_atomically_checked(
+        dst,
+        |tmp| fs::copy(src, tmp).map(|_| ()),
+        |tmp| check_digest(tmp, hash, reading, digest),
+    )
+    .context(format!("Failed to copy file. src={:?}. dst={:?}", src, dst))?;
+    fs::remove_file(src)
+        .context(format!("Failed to remove src file: {:?}", src))?;
+    Ok(())
+}
+
+pub fn check_digest<H: Hasher>(
+    path: &Path,
+    hash: &H,
+    reading: Reading,
+    expected: &str,
+) -> anyhow::Result<()> {
+    let actual = hash
+        .digest(path, reading)
+        .context(format!("Failed to hash file: {:?}", path))?;
+    if actual != expected {
+        anyhow::bail!(
+            "Digest mismatch. path={:?}. expected={:?}. actual={:?}",
+            path,
+            expected,
+            actual
+        );
     }
+    Ok(())
+}
+
+/// Prefix of the hidden temporary files which are written next to their
+/// final destination and then renamed into place.
+pub const TMP_FILE_PREFIX: &str = ".phorg-tmp-";
+
+fn write_atomically<W>(dst: &Path, write: W) -> anyhow::Result<()>
+where
+    W: FnOnce(&Path) -> io::Result<()>,
+{
+    write_atomically_checked(dst, write, |_| Ok(()))
+}
+
+/// Write into a hidden temporary file in the dst directory, sync it, check
+/// it, then rename it to dst, so that a file under its final name is always
+/// complete. The temporary file is removed if any of the steps fail. The
+/// writer is given the path of an existing, empty, temporary file to
+/// overwrite or replace.
+fn write_atomically_checked<W, C>(
+    dst: &Path,
+    write: W,
+    check: C,
+) -> anyhow::Result<()>
+where
+    W: FnOnce(&Path) -> io::Result<()>,
+    C: FnOnce(&Path) -> anyhow::Result<()>,
+{
+    let dir = dst
+        .parent()
+        .ok_or_else(|| anyhow::anyhow!("dst has no parent: {:?}", dst))?;
+    // Created before writing, so that it is removed when dropped on any of
+    // the error paths, including a partial write.
+    let tmp = tempfile::Builder::new()
+        .prefix(TMP_FILE_PREFIX)
+        .tempfile_in(dir)
+        .context(format!("Failed to create temp file in {:?}", dir))?
+        .into_temp_path();
+    write(&tmp).context(format!("Failed to write temp file: {:?}", tmp))?;
+    fs::File::open(&tmp)
+        .and_then(|file| file.sync_all())
+        .context(format!("Failed to sync temp file: {:?}", tmp))?;
+    check(&tmp)?;
+    tmp.persist(dst).context(format!(
+        "Failed to rename temp file into place: {:?}",
+        dst
+    ))?;
+    sync_dir(dir)
+        .context(format!("Failed to sync dst dir after rename: {:?}", dir))?;
+    Ok(())
+}
+
+/// Make the renames in the directory durable.
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    fs::File::open(dir)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// The `<hash name>:<digest>` (or the portable `<hash name>-<digest>`) part
+/// of a file name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DigestTag<'a> {
+    pub digest: &'a str,
+
+    /// Byte range of the whole tag in the name.
+    pub range: ops::Range<usize>,
+
+    /// Whether separated by `-` rather than `:`.
+    pub portable: bool,
+}
+
+/// Find the digest tag of an organized file name, for any of the built-in
+/// hashes. The tag is the hash name, then `:` (or `-` for portable names),
+/// then the hex digest, e.g. `crc32:75daa68d` in
+/// `2022-10-03--17:52:16--crc32:75daa68d.jpg`, and is neither preceded nor
+/// followed by an ASCII alphanumeric character.
+pub fn find_digest(name: &str) -> Option<(Hash, DigestTag<'_>)> {
+    <Hash as clap::ValueEnum>::value_variants()
+        .iter()
+        .find_map(|hash| find_digest_of(name, hash).map(|tag| (*hash, tag)))
+}
+
+/// Find the digest tag of the given hash in an organized file name, laid out
+/// as described for [`find_digest`].
+pub fn find_digest_of<'a, H: Hasher>(
+    name: &'a str,
+    hash: &H,
+) -> Option<DigestTag<'a>> {
+    [':', '-'].into_iter().find_map(|sep| {
+        let tag = format!("{}{}", hash.name(), sep);
+        name.match_indices(&tag).find_map(|(pos, _)| {
+            let is_bounded_before = name[..pos]
+                .chars()
+                .next_back()
+                .is_none_or(|c| !c.is_ascii_alphanumeric());
+            let rest = &name[pos + tag.len()..];
+            let digest = rest.get(..hash.hex_len())?;
+            let is_bounded_after = rest[digest.len()..]
+                .chars()
+                .next()
+                .is_none_or(|c| !c.is_ascii_alphanumeric());
+            (is_bounded_before
+                && is_bounded_after
+                && digest.chars().all(|c| c.is_ascii_hexdigit()))
+            .then(|| DigestTag {
+                digest,
+                range: pos..pos + tag.len() + digest.len(),
+                portable: sep == '-',
+            })
+        })
+    })
 }
 
 fn auxiliary_subpath(
@@ -119,6 +554,7 @@ fn auxiliary_subpath(
     ty: Typ,
     img_dir: &str,
     vid_dir: &str,
+    template: &Template,
 ) -> Option<PathBuf> {
     use std::path::Component;
 
@@ -127,67 +563,153 @@ fn auxiliary_subpath(
         Typ::Img => root.join(img_dir),
         Typ::Vid => root.join(vid_dir),
     };
-    let mid_components: Vec<Component> =
-        parent.strip_prefix(root).ok()?.components().collect();
-    match &mid_components[..] {
-        [Component::Normal(y), Component::Normal(m), Component::Normal(d), subpath @ ..]
-            if are_nums(&[y, m, d]) && !subpath.is_empty() =>
-        {
-            Some(subpath.iter().collect())
+    let mid_components: Vec<&str> = parent
+        .strip_prefix(root)
+        .ok()?
+        .components()
+        .map(|component| match component {
+            Component::Normal(name) => name.to_str(),
+            _ => None,
+        })
+        .collect::<Option<Vec<&str>>>()?;
+    let depth = template.depth();
+    if mid_components.len() > depth
+        && template.matches_dirs(&mid_components[..depth])
+    {
+        Some(mid_components[depth..].iter().collect())
+    } else {
+        None
+    }
+}
+
+/// Parse the name of a directory directly under dst root, rejecting
+/// anything which would lead elsewhere, such as `..` or an absolute path.
+pub fn parse_dir_name(s: &str) -> anyhow::Result<String> {
+    let mut components = Path::new(s).components();
+    match (components.next(), components.next()) {
+        (Some(path::Component::Normal(name)), None) => {
+            Ok(name.to_string_lossy().to_string())
         }
-        _ => None,
+        _ => anyhow::bail!(
+            "Expected the name of a directory directly under DST_ROOT, \
+            got: {:?}",
+            s
+        ),
     }
 }
 
-fn are_nums(strs: &[&OsStr]) -> bool {
-    let count_all = strs.len();
-    let count_nums = strs
-        .iter()
-        .filter_map(|n| n.to_str())
-        .filter_map(|n| n.parse::<usize>().ok())
-        .count();
-    count_all == count_nums
-}
-
-#[tracing::instrument(level = "error")]
-fn read_type(path: &Path) -> Option<Typ> {
-    infer::get_from_path(path)
-        .map(|matcher_type_opt| {
-            tracing::debug!(?matcher_type_opt, "Read");
-            matcher_type_opt.map(|typ| typ.matcher_type()).and_then(
-                |matcher_type| match matcher_type {
-                    infer::MatcherType::Image => Some(Typ::Img),
-                    infer::MatcherType::Video => Some(Typ::Vid),
-                    _ => None,
-                },
-            )
-        })
-        .map_err(|error| {
-            tracing::error!(?error, "Failed");
-        })
-        .ok()
-        .flatten()
+#[derive(Debug)]
+pub struct Options<H: Hasher = Hash> {
+    /// Image subdirectory under dst root.
+    pub img_dir: String,
+
+    /// Video subdirectory under dst root.
+    pub vid_dir: String,
+
+    /// Process only this file type, otherwise all supported.
+    pub ty_filter: Option<Typ>,
+
+    /// Overwrite existing files.
+    pub force: bool,
+
+    /// Fall back on exiftool if we fail to extract Exif data directly.
+    pub use_exiftool: bool,
+
+    /// The exiftool executable.
+    pub exiftool_exe: PathBuf,
+
+    /// Additional arguments to pass to exiftool, e.g. `-api`,
+    /// `largefilesupport=1`.
+    pub exiftool_args: Vec<String>,
+
+    /// Tags to read the timestamp from, in order of priority, both
+    /// natively and via exiftool. The latter is only consulted if a tag of
+    /// higher priority than the one found natively, if any, is one we can't
+    /// read natively. The highest priority tag found by either wins.
+    pub date_tags: Vec<DateTag>,
+
+    /// Chrono formats of the date and time in file names, tried in order
+    /// (after metadata, before filesystem times). Formats without time
+    /// components yield midnight. Empty disables file name parsing.
+    pub name_time_patterns: Vec<String>,
+
+    /// Fall back on filesystem birth time, then mtime, if no timestamp is
+    /// found in metadata.
+    pub use_fs_time: bool,
+
+    /// Camera clock corrections.
+    pub time_shifts: TimeShifts,
+
+    /// Zone in which to express the times in dst paths.
+    pub zone: Zone,
+
+    /// Capture zone assumed for the times recorded in UTC only.
+    pub assumed_zone: AssumedZone,
+
+    /// Show progress bar (when not just showing).
+    pub show_progress: bool,
+
+    /// Digest algorithm of the names.
+    pub hash: H,
+
+    /// How files are read when hashing them.
+    pub reading: Reading,
+
+    /// Layout of the paths under the type directories.
+    pub template: Template,
+
+    /// Avoid characters in names which are illegal on FAT/exFAT and SMB.
+    pub portable_names: bool,
+
+    /// Directory under dst root for the files we could not find a
+    /// timestamp for. If none, such files are skipped.
+    pub unsorted_dir: Option<String>,
+
+    /// Skip files whose content is already anywhere in dst, according to
+    /// the digests in the names of the files there.
+    pub use_index: bool,
+
+    /// Re-hash written files and discard those which do not match the
+    /// digest computed from their src.
+    pub verify: bool,
+}
+
+/// Canonicalize a root path, naming it by its role in the error.
+pub(crate) fn canonicalize(
+    path: &Path,
+    role: &str,
+) -> anyhow::Result<PathBuf> {
+    path.canonicalize()
+        .context(format!("Failed to canonicalize {} path: {:?}", role, path))
+}
+
+/// Progress bar counting files as they are found and processed, drawn only
+/// if shown.
+pub(crate) fn progress_bar(
+    show: bool,
+) -> anyhow::Result<indicatif::ProgressBar> {
+    let progress_bar = if show {
+        indicatif::ProgressBar::new(0)
+    } else {
+        indicatif::ProgressBar::hidden()
+    };
+    let progress_style = indicatif::ProgressStyle::with_template(
+        "{bar:100.green} {pos:>7} / {len:7}",
+    )?;
+    progress_bar.set_style(progress_style);
+    progress_bar.tick();
+    Ok(progress_bar)
 }
 
-#[allow(clippy::too_many_arguments)] // TODO Remove, after combining args.
 #[tracing::instrument(level = "error", skip_all)]
-pub fn organize<'a>(
-    src_root: &'a Path,
-    dst_root: &'a Path,
+pub fn organize<H: Hasher>(
+    src_root: &Path,
+    dst_root: &Path,
     op: &Op,
-    img_dir: &'a str,
-    vid_dir: &'a str,
-    ty_filter: Option<Typ>,
-    force: bool,
-    use_exiftool: bool,
-    show_progress: bool,
-    hash: Hash,
-) -> anyhow::Result<()> {
-    tracing::info!(?op, ?src_root, ?dst_root, "Starting");
-    let src_root = src_root.canonicalize().context(format!(
-        "Failed to canonicalize src path: {:?}",
-        src_root
-    ))?;
+    opts: &Options<H>,
+) -> anyhow::Result<Report> {
+    tracing::info!(?op, ?src_root, ?dst_root, ?opts, "Starting");
+    let src_root = canonicalize(src_root, "src")?;
     if !dst_root.try_exists().context(format!(
         "Failed to check existence of dst path: {:?}",
         &dst_root
@@ -196,205 +718,763 @@ pub fn organize<'a>(
         fs::create_dir_all(dst_root)
             .context(format!("Failed to create dst dir: {:?}", dst_root))?;
     }
-    let dst_root = dst_root.canonicalize().context(format!(
-        "Failed to canonicalize dst path: {:?}",
-        dst_root
-    ))?;
+    let dst_root = canonicalize(dst_root, "dst")?;
     tracing::info!(?src_root, ?dst_root, "Canonicalized");
-    let progress_bar = match op {
-        Op::Copy | Op::Move if show_progress => {
-            indicatif::ProgressBar::new(0)
+    let progress_bar = progress_bar(
+        opts.show_progress
+            && matches!(op, Op::Copy | Op::Move | Op::Link | Op::Reflink),
+    )?;
+    let index = if opts.use_index {
+        Index::build(&dst_root, &opts.hash)
+    } else {
+        Index::default()
+    };
+    let run_id = manifest::new_run_id();
+    let manifest = match op {
+        Op::Show => None,
+        Op::Copy | Op::Move | Op::Link | Op::Reflink => {
+            Some(manifest::Writer::open(&dst_root)?)
         }
-        _ => indicatif::ProgressBar::hidden(),
     };
-    let progress_style = indicatif::ProgressStyle::with_template(
-        "{bar:100.green} {pos:>7} / {len:7}",
-    )?;
-    progress_bar.set_style(progress_style);
-    progress_bar.tick();
-    FilePaths::find(&src_root)
+    tracing::info!(?run_id, "Run");
+    let exiftool = opts
+        .use_exiftool
+        .then(|| exiftool::Pool::new(&opts.exiftool_exe, &opts.exiftool_args))
+        .filter(|exiftool| match exiftool.probe() {
+            Ok(version) => {
+                tracing::info!(?version, "Found exiftool.");
+                eprintln!("Using exiftool {}", version);
+                true
+            }
+            Err(error) => {
+                tracing::error!(
+                    "exiftool fallback disabled, since it is unavailable: \
+                    {:#}",
+                    error
+                );
+                false
+            }
+        });
+    let run = Run {
+        src_root: &src_root,
+        dst_root: &dst_root,
+        op,
+        opts,
+        index,
+        manifest,
+        exiftool,
+        run_id: &run_id,
+    };
+    let report = FilePaths::find(&src_root)
         .par_bridge()
-        .filter_map(|p| {
+        .map(|path| {
             progress_bar.inc_length(1);
-            read_type(&p).map(|t| (p, t))
+            let status = run.process(&path);
+            tracing::debug!(?path, ?status, "Processed");
+            progress_bar.inc(1);
+            status
         })
-        .filter_map(move |(path, ty_found)| {
-            tracing::debug!(?path, ?ty_filter, ?ty_found, "Type filter");
-            match ty_filter {
-                Some(ty_filter) if ty_filter == ty_found => {
-                    Some((path, ty_found))
-                }
-                None => Some((path, ty_found)),
-                Some(_) => None,
+        .fold(Report::default, Report::add)
+        .reduce(Report::default, Report::merge);
+    progress_bar.finish();
+    let report = Report { run_id, ..report };
+    tracing::info!(?report, "Finished");
+    Ok(report)
+}
+
+/// Final status of a file found in src.
+#[derive(Debug)]
+enum Status {
+    Shown,
+    Organized,
+    Unsorted,
+    Present,
+    Filtered,
+    Unsupported,
+    NoTimestamp,
+    HashError,
+    IoError,
+    Unrecorded,
+}
+
+/// Counts of files found in src, by what happened to them.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub run_id: String,
+
+    /// Would have been organized, but op was show.
+    pub shown: usize,
+
+    pub organized: usize,
+
+    /// Organized into the unsorted directory, for lack of a timestamp.
+    pub unsorted: usize,
+
+    /// Content already in dst.
+    pub present: usize,
+
+    /// Excluded by the type filter.
+    pub skipped_filtered: usize,
+
+    /// Neither an image nor a video.
+    pub skipped_unsupported: usize,
+
+    pub skipped_no_timestamp: usize,
+    pub hash_errors: usize,
+    pub io_errors: usize,
+
+    /// Organized, but not recorded in the manifest, so unknown to undo.
+    pub unrecorded: usize,
+}
+
+impl Report {
+    /// Files which we failed to process, as opposed to skipped.
+    pub fn failures(&self) -> usize {
+        self.hash_errors + self.io_errors + self.unrecorded
+    }
+
+    fn add(mut self, status: Status) -> Self {
+        match status {
+            Status::Shown => self.shown += 1,
+            Status::Organized => self.organized += 1,
+            Status::Unsorted => self.unsorted += 1,
+            Status::Present => self.present += 1,
+            Status::Filtered => self.skipped_filtered += 1,
+            Status::Unsupported => self.skipped_unsupported += 1,
+            Status::NoTimestamp => self.skipped_no_timestamp += 1,
+            Status::HashError => self.hash_errors += 1,
+            Status::IoError => self.io_errors += 1,
+            Status::Unrecorded => self.unrecorded += 1,
+        }
+        self
+    }
+
+    fn merge(self, other: Self) -> Self {
+        Self {
+            run_id: self.run_id,
+            shown: self.shown + other.shown,
+            organized: self.organized + other.organized,
+            unsorted: self.unsorted + other.unsorted,
+            present: self.present + other.present,
+            skipped_filtered: self.skipped_filtered + other.skipped_filtered,
+            skipped_unsupported: self.skipped_unsupported
+                + other.skipped_unsupported,
+            skipped_no_timestamp: self.skipped_no_timestamp
+                + other.skipped_no_timestamp,
+            hash_errors: self.hash_errors + other.hash_errors,
+            io_errors: self.io_errors + other.io_errors,
+            unrecorded: self.unrecorded + other.unrecorded,
+        }
+    }
+}
+
+/// State shared by all files of an organize run.
+struct Run<'a, H: Hasher> {
+    src_root: &'a Path,
+    dst_root: &'a Path,
+    op: &'a Op,
+    opts: &'a Options<H>,
+    index: Index,
+    manifest: Option<manifest::Writer>,
+    exiftool: Option<exiftool::Pool>,
+    run_id: &'a str,
+}
+
+impl<H: Hasher> Run<'_, H> {
+    fn process(&self, path: &Path) -> Status {
+        let opts = self.opts;
+        // Opened once, for type, metadata and digest.
+        let mut source = match Source::open(path, &opts.hash, opts.reading) {
+            Err(error) => {
+                tracing::error!(?path, ?error, "Failed to read type");
+                return Status::IoError;
             }
-        })
-        .filter_map(move |(path, typ)| {
-            read_timestamp(&path, typ, use_exiftool)
-                .ok()
-                .flatten()
-                .map(|timestamp| (path, typ, timestamp))
-        })
-        .filter_map(move |(path, typ, timestamp)| {
-            hash.digest(&path)
-                .ok()
-                .map(|digest| (path, typ, timestamp, digest))
-        })
-        .map(move |(path, typ, timestamp, digest)| {
-            File::new(
-                &src_root, &path, typ, img_dir, vid_dir, timestamp, hash,
-                &digest,
-            )
-        })
-        .for_each(|file| {
-            let result = match op {
-                Op::Show => {
-                    file.show(&dst_root);
-                    Ok(())
-                }
-                Op::Copy => file.organize(&dst_root, false, force),
-                Op::Move => file.organize(&dst_root, true, force),
-            };
-            if let Err(error) = result {
-                tracing::error!(?error, ?file, "Failed to organize");
+            Ok(source) => source,
+        };
+        let Some(typ) = source.typ() else {
+            return Status::Unsupported;
+        };
+        match opts.ty_filter {
+            Some(ty_filter) if ty_filter != typ => return Status::Filtered,
+            Some(_) | None => {}
+        }
+        let meta = match read_meta(
+            path,
+            &mut source,
+            typ,
+            opts,
+            self.exiftool.as_ref(),
+        ) {
+            Err(error) => {
+                tracing::error!(?path, ?error, "Failed to read metadata");
+                return Status::IoError;
             }
-            progress_bar.inc(1);
-        });
-    progress_bar.finish();
-    tracing::info!("Finished");
-    Ok(())
+            Ok(meta) => meta,
+        };
+        let timestamp = match (meta.timestamp, &opts.unsorted_dir) {
+            (Some(timestamp), _) => timestamp,
+            (None, Some(unsorted_dir)) => {
+                tracing::warn!(?path, "No timestamp found. Unsorted.");
+                let Some(digest) = digest(path, source) else {
+                    return Status::HashError;
+                };
+                let file =
+                    File::unsorted(path, typ, &digest, unsorted_dir, opts);
+                return match self.organize(&file) {
+                    Status::Organized => Status::Unsorted,
+                    status => status,
+                };
+            }
+            (None, None) => {
+                tracing::warn!(?path, "Skipping. No timestamp found.");
+                return Status::NoTimestamp;
+            }
+        };
+        let timestamp = (
+            opts.time_shifts.apply(timestamp.0, meta.model.as_deref()),
+            timestamp.1,
+        );
+        let Some(digest) = digest(path, source) else {
+            return Status::HashError;
+        };
+        let file = File::new(
+            self.src_root,
+            path,
+            typ,
+            timestamp,
+            meta.model.as_deref(),
+            &digest,
+            opts,
+        );
+        self.organize(&file)
+    }
+
+    fn organize(&self, file: &File<H>) -> Status {
+        let dst_root = self.dst_root;
+        let existing = self.index.find_elsewhere(
+            &file.digest,
+            &file.src,
+            &dst_root.join(&file.dst),
+        );
+        let (result, dst) = match (self.op, existing) {
+            (_, Some(existing)) => {
+                tracing::info!(
+                    ?file,
+                    ?existing,
+                    "Skipping. Content already in dst."
+                );
+                let dst = existing
+                    .strip_prefix(dst_root)
+                    .unwrap_or(existing)
+                    .to_path_buf();
+                (Ok(Outcome::PresentElsewhere), dst)
+            }
+            (Op::Show, None) => {
+                file.show(dst_root);
+                return Status::Shown;
+            }
+            (Op::Copy | Op::Move | Op::Link | Op::Reflink, None) => (
+                file.organize(
+                    dst_root,
+                    self.op,
+                    self.opts.force,
+                    self.opts.verify,
+                ),
+                file.dst.clone(),
+            ),
+        };
+        if let Err(error) = &result {
+            tracing::error!(?error, ?file, "Failed to organize");
+        }
+        let recorded = match &self.manifest {
+            None => true,
+            Some(manifest) => {
+                let entry =
+                    file.manifest_entry(self.run_id, &dst, *self.op, &result);
+                manifest
+                    .append(&entry)
+                    .map_err(|error| {
+                        tracing::error!(?error, ?entry, "Failed to record");
+                    })
+                    .is_ok()
+            }
+        };
+        match result {
+            Ok(Outcome::Organized) if !recorded => Status::Unrecorded,
+            Ok(Outcome::Organized) => Status::Organized,
+            Ok(Outcome::Present | Outcome::PresentElsewhere) => {
+                Status::Present
+            }
+            Ok(Outcome::Failed | Outcome::Undone | Outcome::Rehashed)
+            | Err(_) => Status::IoError,
+        }
+    }
 }
 
 // Ref: exif::tag::d_datetime (private).
-fn get_date_time_original(exif: &exif::Exif) -> Option<exif::DateTime> {
-    exif.get_field(exif::Tag::DateTimeOriginal, exif::In::PRIMARY)
+/// Date and time from the given tag, with the offset and subseconds from
+/// the corresponding tags, when present.
+fn get_date_time(
+    exif: &exif::Exif,
+    tag: exif::Tag,
+    offset_tag: exif::Tag,
+    subsec_tag: exif::Tag,
+) -> Option<exif::DateTime> {
+    let ascii = |tag| {
+        exif.get_field(tag, exif::In::PRIMARY)
+            .and_then(|field| match &field.value {
+                exif::Value::Ascii(data) => data.first(),
+                _ => None,
+            })
+    };
+    let mut dt =
+        ascii(tag).and_then(|data| exif::DateTime::from_ascii(data).ok())?;
+    if let Some(data) = ascii(offset_tag) {
+        if let Err(error) = dt.parse_offset(data) {
+            tracing::warn!(?error, %offset_tag, "Invalid offset");
+        }
+    }
+    if let Some(data) = ascii(subsec_tag) {
+        if let Err(error) = dt.parse_subsec(data) {
+            tracing::warn!(?error, %subsec_tag, "Invalid subseconds");
+        }
+    }
+    Some(dt)
+}
+
+fn get_model(exif: &exif::Exif) -> Option<String> {
+    exif.get_field(exif::Tag::Model, exif::In::PRIMARY)
         .and_then(|field| match &field.value {
-            exif::Value::Ascii(data) => Some(data),
+            exif::Value::Ascii(data) => data.first(),
             _ => None,
         })
-        .and_then(|data| data.first())
-        .and_then(|data| exif::DateTime::from_ascii(data).ok())
+        .map(|data| String::from_utf8_lossy(data).trim().to_string())
+        .filter(|model| !model.is_empty())
 }
 
-fn date_time_exif_to_chrono(
-    dt: &exif::DateTime,
-) -> Option<chrono::NaiveDateTime> {
-    let time = chrono::NaiveTime::from_hms_opt(
+fn date_time_exif_to_timestamp(dt: &exif::DateTime) -> Option<Timestamp> {
+    let time = chrono::NaiveTime::from_hms_nano_opt(
         u32::from(dt.hour),
         u32::from(dt.minute),
         u32::from(dt.second),
+        dt.nanosecond.unwrap_or(0),
     )?;
     let date = chrono::NaiveDate::from_ymd_opt(
         i32::from(dt.year),
         u32::from(dt.month),
         u32::from(dt.day),
     )?;
-    Some(chrono::NaiveDateTime::new(date, time))
+    let offset = dt.offset.and_then(|minutes| {
+        chrono::FixedOffset::east_opt(i32::from(minutes) * 60)
+    });
+    Some(Timestamp::new(
+        chrono::NaiveDateTime::new(date, time),
+        offset,
+    ))
 }
 
-fn dst(
+fn dst<H: Hasher>(
     root: &Path,
     src: &Path,
     typ: Typ,
-    img_dir: &str,
-    vid_dir: &str,
     ts: Timestamp,
-    hash_name: &str,
+    model: Option<&str>,
     digest: &str,
+    opts: &Options<H>,
 ) -> PathBuf {
-    use chrono::{Datelike, Timelike}; // Access timestamp fields.
-
-    let year = format!("{:02}", ts.year());
-    let month = format!("{:02}", ts.month());
-    let day = format!("{:02}", ts.day());
-    let hour = format!("{:02}", ts.hour());
-    let minute = format!("{:02}", ts.minute());
-    let second = format!("{:02}", ts.second());
-
-    let stem = [
-        [year.as_str(), month.as_str(), day.as_str()].join("-"),
-        [hour, minute, second].join(":"),
-        [hash_name, digest].join(":"),
-    ]
-    .join("--");
-
     let extension = src.extension().unwrap_or_default().to_ascii_lowercase();
-    let name = PathBuf::from(stem).with_extension(extension);
+    let vars = template::Vars {
+        timestamp: ts.in_zone(opts.zone, opts.assumed_zone),
+        hash_name: opts.hash.name(),
+        digest,
+        model,
+        ext: &extension.to_string_lossy(),
+        portable: opts.portable_names,
+    };
     let typ_dir = match typ {
-        Typ::Img => img_dir,
-        Typ::Vid => vid_dir,
+        Typ::Img => &opts.img_dir,
+        Typ::Vid => &opts.vid_dir,
     };
-    let mut dir: PathBuf = [typ_dir, &year, &month, &day].iter().collect();
-    if let Some(aux) = auxiliary_subpath(root, src, typ, img_dir, vid_dir) {
+    let mut dir =
+        PathBuf::from(typ_dir).join(opts.template.render_dir(&vars));
+    if let Some(aux) = auxiliary_subpath(
+        root,
+        src,
+        typ,
+        &opts.img_dir,
+        &opts.vid_dir,
+        &opts.template,
+    ) {
         dir.push(aux);
     }
-    dir.join(name)
+    dir.join(opts.template.render_name(&vars))
 }
 
 #[tracing::instrument(level = "error", skip_all, fields(path = ?path))]
-fn read_timestamp(
+fn read_meta<H: Hasher>(
     path: &Path,
+    source: &mut Source<H>,
     typ: Typ,
-    use_exiftool: bool,
-) -> anyhow::Result<Option<Timestamp>> {
-    let file = fs::File::open(path)?;
-    let timestamp: Option<Timestamp> = match typ {
-        Typ::Img => read_timestamp_img(&file),
-        Typ::Vid => read_timestamp_vid(&file),
-    }
-    .or_else(|| {
-        use_exiftool
-            .then(|| exiftool::read_timestamp(path))
-            .flatten()
+    opts: &Options<H>,
+    exiftool: Option<&exiftool::Pool>,
+) -> anyhow::Result<Meta> {
+    io::Seek::rewind(source)?;
+    let tags = &opts.date_tags[..];
+    let (native, readable) = match typ {
+        Typ::Img => read_meta_img(&mut *source, tags),
+        Typ::Vid => read_meta_vid(&mut *source, tags),
+    };
+    let mut meta = merge_meta(native, readable, tags, || {
+        exiftool.and_then(|exiftool| exiftool.read_meta(path, tags))
     });
-    tracing::debug!(?timestamp, "Finished");
-    Ok(timestamp)
+    if meta.timestamp.is_none() {
+        meta.timestamp = path
+            .file_name()
+            .and_then(|name| {
+                read_name_time(
+                    &name.to_string_lossy(),
+                    &opts.name_time_patterns,
+                )
+            })
+            .map(|t| (Timestamp::from(t), TimestampSource::Filename));
+    }
+    if meta.timestamp.is_none() && opts.use_fs_time {
+        meta.timestamp = read_fs_time(source.metadata());
+    }
+    tracing::debug!(?meta, "Finished");
+    Ok(meta)
+}
+
+/// Meta read natively, or via exiftool if that finds a tag of higher
+/// priority. exiftool is only consulted if it might, i.e. if any tag of
+/// higher priority than the one found natively is not natively readable.
+fn merge_meta(
+    native: Meta,
+    readable: &[DateTag],
+    tags: &[DateTag],
+    read_exiftool: impl FnOnce() -> Option<Meta>,
+) -> Meta {
+    let outranked = tags[..native.rank(tags)]
+        .iter()
+        .any(|tag| !readable.contains(tag));
+    match outranked.then(read_exiftool).flatten() {
+        Some(other) if other.rank(tags) < native.rank(tags) => {
+            other.or(native)
+        }
+        Some(other) => native.or(other),
+        None => native,
+    }
+}
+
+/// Built-in file name timestamp formats, e.g.:
+/// - `IMG_20200131_173549.jpg`, `VID_20200131_173549.mp4` (Android)
+/// - `Screenshot_2020-01-31-17-35-49.png`
+/// - `2020-01-31 17.35.49.jpg` (Dropbox camera uploads)
+/// - `2020-01-31--17:35:49--crc32:75daa68d.jpg` (ours)
+/// - `IMG-20200131-WA0003.jpg` (WhatsApp, date only)
+///
+/// A bare `%Y%m%d` is left out, since it would match any run of 8 digits,
+/// e.g. a camera's file counter. It can be opted into as a user pattern.
+pub const NAME_TIME_PATTERNS: &[&str] = &[
+    "%Y%m%d_%H%M%S",
+    "%Y%m%d-%H%M%S",
+    "%Y-%m-%d-%H-%M-%S",
+    "%Y-%m-%d_%H-%M-%S",
+    "%Y-%m-%d %H.%M.%S",
+    "%Y-%m-%d--%H:%M:%S",
+    "%Y-%m-%d--%H-%M-%S",
+    "%Y%m%d-WA",
+];
+
+/// First date/time found in the name, by the first of the patterns which
+/// matches and, if the match ends in a digit, is not followed by one.
+/// Patterns starting with a field are tried at the start of each run of
+/// digits, patterns starting with a literal (e.g. `IMG_%Y%m%d`) at every
+/// character.
+fn read_name_time(
+    name: &str,
+    patterns: &[String],
+) -> Option<chrono::NaiveDateTime> {
+    let is_digit_at = |s: &str, i: usize| {
+        s[i..].chars().next().is_some_and(|c| c.is_ascii_digit())
+    };
+    let digit_runs: Vec<usize> = name
+        .char_indices()
+        .filter(|(i, c)| {
+            c.is_ascii_digit()
+                && !name[..*i].ends_with(|c: char| c.is_ascii_digit())
+        })
+        .map(|(i, _)| i)
+        .collect();
+    let chars: Vec<usize> = name.char_indices().map(|(i, _)| i).collect();
+    patterns.iter().find_map(|pattern| {
+        let field_first =
+            pattern.starts_with('%') && !pattern.starts_with("%%");
+        let offsets = if field_first { &digit_runs } else { &chars };
+        offsets.iter().find_map(|i| {
+            let s = &name[*i..];
+            let (timestamp, rest) =
+                chrono::NaiveDateTime::parse_and_remainder(s, pattern)
+                    .or_else(|_| {
+                        chrono::NaiveDate::parse_and_remainder(s, pattern)
+                            .map(|(date, rest)| {
+                                (date.and_time(Default::default()), rest)
+                            })
+                    })
+                    .ok()?;
+            let matched = &s[..s.len() - rest.len()];
+            let ends_in_digit =
+                matched.ends_with(|c: char| c.is_ascii_digit());
+            (!ends_in_digit || !is_digit_at(rest, 0)).then_some(timestamp)
+        })
+    })
 }
 
+/// Birth time, if the platform and filesystem support it, otherwise mtime,
+/// in this machine's zone.
+fn read_fs_time(
+    metadata: &fs::Metadata,
+) -> Option<(Timestamp, TimestampSource)> {
+    let local = |t| {
+        Timestamp::from_date_time(&chrono::DateTime::<chrono::Local>::from(t))
+    };
+    match (metadata.created(), metadata.modified()) {
+        (Ok(t), _) => Some((local(t), TimestampSource::Birthtime)),
+        (Err(_), Ok(t)) => Some((local(t), TimestampSource::Mtime)),
+        (Err(_), Err(_)) => None,
+    }
+}
+
+/// Meta read natively from the first of the tags found, along with the tags
+/// that could be read at all.
 #[tracing::instrument(level = "error", skip_all)]
-fn read_timestamp_img(file: &fs::File) -> Option<Timestamp> {
-    let mut bufreader = std::io::BufReader::new(file);
+fn read_meta_img<R: io::Read + io::Seek>(
+    reader: R,
+    tags: &[DateTag],
+) -> (Meta, &'static [DateTag]) {
+    use exif::Tag;
+
+    let mut bufreader = std::io::BufReader::new(reader);
     exif::Reader::new()
         .read_from_container(&mut bufreader)
         .map_err(|error| {
             tracing::error!(?error, "exif read_from_container failed.");
         })
-        .ok()
-        .and_then(|exif| {
-            get_date_time_original(&exif)
-                .as_ref()
-                .and_then(date_time_exif_to_chrono)
+        .map(|exif| {
+            let found = tags.iter().find_map(|tag| {
+                let dt = match tag {
+                    DateTag::DateTimeOriginal => get_date_time(
+                        &exif,
+                        Tag::DateTimeOriginal,
+                        Tag::OffsetTimeOriginal,
+                        Tag::SubSecTimeOriginal,
+                    ),
+                    DateTag::CreateDate => get_date_time(
+                        &exif,
+                        Tag::DateTimeDigitized,
+                        Tag::OffsetTimeDigitized,
+                        Tag::SubSecTimeDigitized,
+                    ),
+                    _ => None,
+                };
+                let t = dt.as_ref().and_then(date_time_exif_to_timestamp)?;
+                Some((t, *tag))
+            });
+            let meta = Meta {
+                timestamp: found.map(|(t, _)| (t, TimestampSource::Native)),
+                tag: found.map(|(_, tag)| tag),
+                model: get_model(&exif),
+            };
+            (meta, EXIF_DATE_TAGS)
         })
+        .unwrap_or_default()
 }
 
+/// Meta read natively from the first of the tags found, along with the tags
+/// that could be read at all.
 #[tracing::instrument(level = "error", skip_all)]
-fn read_timestamp_vid(file: &fs::File) -> Option<Timestamp> {
+fn read_meta_vid<R: io::Read + io::Seek>(
+    reader: R,
+    tags: &[DateTag],
+) -> (Meta, &'static [DateTag]) {
+    use chrono::Timelike; // Set subseconds.
     use nom_exif::{
         EntryValue, Exif, ExifIter, ExifTag, MediaParser, MediaSource,
         TrackInfo, TrackInfoTag,
     };
 
+    fn text(entry: &EntryValue) -> Option<String> {
+        match entry {
+            EntryValue::Text(text) => Some(text.clone()),
+            _ => None,
+        }
+    }
+
     // TODO Should a parser instace be re-used for multiple files?
     let mut parser = MediaParser::new();
-    let source = MediaSource::seekable(file).ok()?;
+    let Ok(source) = MediaSource::seekable(reader) else {
+        return Default::default();
+    };
 
     if source.has_track() {
-        let info: TrackInfo = parser.parse(source).ok()?;
-        match info.get(TrackInfoTag::CreateDate)? {
-            EntryValue::Time(t) => Some(t.naive_local()),
+        let Ok(info): Result<TrackInfo, _> = parser.parse(source) else {
+            return Default::default();
+        };
+        // Either the mvhd creation time, which is in UTC only, or Apple's
+        // creationdate, which carries the capture offset. nom-exif does not
+        // tell us which, so it stands for any of the QuickTime tags, and a
+        // zero offset is taken for the former.
+        let create_date = || match info.get(TrackInfoTag::CreateDate)? {
+            EntryValue::Time(t) if t.offset().local_minus_utc() == 0 => {
+                Some(Timestamp::from_utc(t.naive_utc()))
+            }
+            EntryValue::Time(t) => Some(Timestamp::from_date_time(t)),
             _ => None,
-        }
+        };
+        let found = tags
+            .iter()
+            .find(|tag| TRACK_DATE_TAGS.contains(tag))
+            .and_then(|tag| Some((create_date()?, *tag)));
+        let meta = Meta {
+            timestamp: found.map(|(t, _)| (t, TimestampSource::Native)),
+            tag: found.map(|(_, tag)| tag),
+            model: info.get(TrackInfoTag::Model).and_then(text),
+        };
+        (meta, TRACK_DATE_TAGS)
     } else if source.has_exif() {
-        let entries: ExifIter = parser.parse(source).ok()?;
+        let Ok(entries): Result<ExifIter, _> = parser.parse(source) else {
+            return Default::default();
+        };
         let entries: Exif = entries.into();
-        entries
-            .get(ExifTag::DateTimeOriginal)
-            .or_else(|| entries.get(ExifTag::CreateDate))
-            .and_then(|entry| match entry {
-                EntryValue::Time(t) => Some(t.naive_local()),
+        let get = |tag, offset_tag, subsec_tag| {
+            // Without the offset tag, there's no offset.
+            let (local, offset) = entries.get(tag)?.as_time_components()?;
+            let offset = entries
+                .get(offset_tag)
+                .and_then(text)
+                .and_then(|offset| {
+                    timestamp::parse_offset(offset.trim()).ok()
+                })
+                .or(offset);
+            let local = entries
+                .get(subsec_tag)
+                .and_then(text)
+                .and_then(|subsec| timestamp::parse_subsec(&subsec))
+                .and_then(|nanos| local.with_nanosecond(nanos))
+                .unwrap_or(local);
+            Some(Timestamp::new(local, offset))
+        };
+        let found = tags.iter().find_map(|tag| {
+            let t = match tag {
+                DateTag::DateTimeOriginal => get(
+                    ExifTag::DateTimeOriginal,
+                    ExifTag::OffsetTimeOriginal,
+                    ExifTag::SubSecTimeOriginal,
+                ),
+                DateTag::CreateDate => get(
+                    ExifTag::CreateDate,
+                    ExifTag::OffsetTimeDigitized,
+                    ExifTag::SubSecTimeDigitized,
+                ),
                 _ => None,
-            })
+            }?;
+            Some((t, *tag))
+        });
+        let meta = Meta {
+            timestamp: found.map(|(t, _)| (t, TimestampSource::Native)),
+            tag: found.map(|(_, tag)| tag),
+            model: entries.get(ExifTag::Model).and_then(text),
+        };
+        (meta, EXIF_DATE_TAGS)
     } else {
-        None
+        Default::default()
+    }
+}
+
+/// Digest of the rest of the source, logging failure.
+fn digest<H: Hasher>(path: &Path, source: Source<H>) -> Option<String> {
+    source
+        .digest()
+        .map_err(|error| {
+            tracing::error!(?path, ?error, "Failed to hash file");
+        })
+        .ok()
+}
+
+/// Locations of the files in dst, by the digests in their names.
+#[derive(Debug, Default)]
+struct Index {
+    paths: HashMap<String, Vec<PathBuf>>,
+}
+
+impl Index {
+    #[tracing::instrument(level = "error", skip_all)]
+    fn build<H: Hasher>(dst_root: &Path, hash: &H) -> Self {
+        let mut paths: HashMap<String, Vec<PathBuf>> = HashMap::new();
+        for path in FilePaths::find(dst_root) {
+            let digest = path
+                .file_name()
+                .map(|name| name.to_string_lossy())
+                .as_deref()
+                .and_then(|name| find_digest_of(name, hash))
+                .map(|tag| tag.digest.to_string());
+            if let Some(digest) = digest {
+                paths.entry(digest).or_default().push(path);
+            }
+        }
+        tracing::info!(digests = paths.len(), "Built");
+        Self { paths }
+    }
+
+    /// Find a file with the digest and content of src, other than src
+    /// itself and the given dst. The digest alone is not enough, since a
+    /// short one, such as crc32's, collides easily in a large library.
+    fn find_elsewhere(
+        &self,
+        digest: &str,
+        src: &Path,
+        dst: &Path,
+    ) -> Option<&Path> {
+        self.paths
+            .get(digest)?
+            .iter()
+            .filter(|existing| *existing != src && *existing != dst)
+            .find(|existing| {
+                same_content(src, existing)
+                    .map_err(|error| {
+                        tracing::error!(
+                            ?src,
+                            ?existing,
+                            ?error,
+                            "Failed to compare"
+                        );
+                    })
+                    .unwrap_or(false)
+            })
+            .map(PathBuf::as_path)
+    }
+}
+
+/// Whether the files have the same size and bytes.
+fn same_content(a: &Path, b: &Path) -> io::Result<bool> {
+    use io::Read;
+
+    let (mut a, mut b) = (fs::File::open(a)?, fs::File::open(b)?);
+    if a.metadata()?.len() != b.metadata()?.len() {
+        return Ok(false);
+    }
+    let mut buf_a = vec![0; 64 * 1024];
+    let mut buf_b = vec![0; 64 * 1024];
+    loop {
+        let n = a.read(&mut buf_a)?;
+        if n == 0 {
+            // Same size, so b is at its end too.
+            return Ok(true);
+        }
+        b.read_exact(&mut buf_b[..n])?;
+        if buf_a[..n] != buf_b[..n] {
+            return Ok(false);
+        }
     }
 }
 
@@ -466,56 +1546,478 @@ impl Iterator for FilePaths {
 mod tests {
     use super::*;
 
+    #[test]
+    fn t_find_digest() {
+        let digest =
+            |name| find_digest(name).map(|(hash, tag)| (hash, tag.digest));
+
+        let name = "2022-10-03--17:52:16--crc32:75daa68d.jpg";
+        assert_eq!(Some((Hash::Crc32, "75daa68d")), digest(name));
+
+        let name = "2022-10-03--17:52:16--md5:\
+            d41d8cd98f00b204e9800998ecf8427e.jpg";
+        assert_eq!(
+            Some((Hash::Md5, "d41d8cd98f00b204e9800998ecf8427e")),
+            digest(name)
+        );
+
+        let name = "2022-10-03--17-52-16--xxh128-\
+            99aa06d3014798d86001c324468d497f.jpg";
+        assert_eq!(
+            Some((Hash::Xxh128, "99aa06d3014798d86001c324468d497f")),
+            digest(name)
+        );
+
+        // Too short:
+        let name = "2022-10-03--17:52:16--crc32:75daa68.jpg";
+        assert_eq!(None, digest(name));
+
+        // Too long:
+        let name = "2022-10-03--17:52:16--crc32:75daa68d0.jpg";
+        assert_eq!(None, digest(name));
+
+        // Not hex:
+        let name = "2022-10-03--17:52:16--crc32:75daa68x.jpg";
+        assert_eq!(None, digest(name));
+
+        // Unknown hash:
+        let name = "2022-10-03--17:52:16--foo:75daa68d.jpg";
+        assert_eq!(None, digest(name));
+
+        // Portable:
+        let name = "2022-10-03--17-52-16--crc32-75daa68d.jpg";
+        assert_eq!(Some((Hash::Crc32, "75daa68d")), digest(name));
+
+        let name = "IMG_1234.jpg";
+        assert_eq!(None, digest(name));
+
+        // Located exactly, past lookalikes which are not bounded.
+        let name = "xcrc32:75daa68d--crc32:75daa68d.jpg";
+        let (_, tag) = find_digest(name).unwrap();
+        assert_eq!((17..31, false), (tag.range.clone(), tag.portable));
+        assert_eq!("crc32:75daa68d", &name[tag.range]);
+        let name = "2022-10-03--17-52-16--crc32-75daa68d.jpg";
+        let (_, tag) = find_digest(name).unwrap();
+        assert_eq!((22..36, true), (tag.range, tag.portable));
+    }
+
+    #[test]
+    fn t_read_name_time() {
+        let patterns: Vec<String> =
+            NAME_TIME_PATTERNS.iter().map(|p| p.to_string()).collect();
+        let ts = |date: &str, time: &str| {
+            Some(chrono::NaiveDateTime::new(
+                date.parse().unwrap(),
+                time.parse().unwrap(),
+            ))
+        };
+        assert_eq!(
+            ts("2020-01-31", "17:35:49"),
+            read_name_time("IMG_20200131_173549.jpg", &patterns)
+        );
+        assert_eq!(
+            ts("2020-01-31", "17:35:49"),
+            read_name_time("VID_20200131_173549.mp4", &patterns)
+        );
+        assert_eq!(
+            ts("2020-01-31", "00:00:00"),
+            read_name_time("IMG-20200131-WA0003.jpg", &patterns)
+        );
+        assert_eq!(
+            ts("2020-01-31", "17:35:49"),
+            read_name_time("Screenshot_2020-01-31-17-35-49.png", &patterns)
+        );
+        assert_eq!(
+            ts("2020-01-31", "17:35:49"),
+            read_name_time("2020-01-31 17.35.49.jpg", &patterns)
+        );
+        assert_eq!(
+            ts("2020-01-31", "17:35:49"),
+            read_name_time(
+                "2020-01-31--17:35:49--crc32:75daa68d.jpg",
+                &patterns
+            )
+        );
+        assert_eq!(None, read_name_time("IMG_1234.jpg", &patterns));
+        assert_eq!(None, read_name_time("IMG_202001311.jpg", &patterns));
+        assert_eq!(None, read_name_time("foo.jpg", &patterns));
+        assert_eq!(None, read_name_time("DSC_20200131.jpg", &patterns));
+        assert_eq!(None, read_name_time("IMG_20200131_173549.jpg", &[]));
+
+        let patterns = vec!["%d.%m.%Y".to_string()];
+        assert_eq!(
+            ts("2020-01-31", "00:00:00"),
+            read_name_time("scan 31.01.2020.png", &patterns)
+        );
+
+        // Opted into.
+        let patterns = vec!["%Y%m%d".to_string()];
+        assert_eq!(
+            ts("2020-01-31", "00:00:00"),
+            read_name_time("DSC_20200131.jpg", &patterns)
+        );
+        assert_eq!(None, read_name_time("DSC_202001311.jpg", &patterns));
+
+        // Leading literal.
+        let patterns = vec!["IMG_%Y%m%d".to_string()];
+        assert_eq!(
+            ts("2020-01-31", "00:00:00"),
+            read_name_time("IMG_20200131.jpg", &patterns)
+        );
+        assert_eq!(
+            ts("2020-01-31", "00:00:00"),
+            read_name_time("trip IMG_20200131 (1).jpg", &patterns)
+        );
+        assert_eq!(None, read_name_time("DSC_20200131.jpg", &patterns));
+        assert_eq!(None, read_name_time("IMG_202001311.jpg", &patterns));
+    }
+
     #[test]
     fn t_auxiliary_subpath() {
         let root = PathBuf::from("/a/b/c");
         let ty = Typ::Img;
         let img_dir = "img";
         let vid_dir = "vid";
+        let template = Template::default();
 
         // Single level aux subdir:
         let path = PathBuf::from("/a/b/c/img/2009/01/07/foo/file.jpg");
-        let aux = auxiliary_subpath(&root, &path, ty, img_dir, vid_dir);
+        let aux =
+            auxiliary_subpath(&root, &path, ty, img_dir, vid_dir, &template);
         assert_eq!(Some(PathBuf::from("foo")), aux);
 
         // Multi level aux subdir:
         let path =
             PathBuf::from("/a/b/c/img/2009/01/07/foo/bar/baz/file.jpg");
-        let aux = auxiliary_subpath(&root, &path, ty, img_dir, vid_dir);
+        let aux =
+            auxiliary_subpath(&root, &path, ty, img_dir, vid_dir, &template);
         assert_eq!(Some(PathBuf::from("foo/bar/baz")), aux);
 
         // No aux subdir:
         let path = PathBuf::from("/a/b/c/img/2009/01/07/file.jpg");
-        let aux = auxiliary_subpath(&root, &path, ty, img_dir, vid_dir);
+        let aux =
+            auxiliary_subpath(&root, &path, ty, img_dir, vid_dir, &template);
         assert_eq!(None, aux);
 
         // Not proper date path:
         let path = PathBuf::from("/a/b/c/img/2009/01/foo/file.jpg");
-        let aux = auxiliary_subpath(&root, &path, ty, img_dir, vid_dir);
+        let aux =
+            auxiliary_subpath(&root, &path, ty, img_dir, vid_dir, &template);
         assert_eq!(None, aux);
 
         // Relative path:
         let root = PathBuf::from("/a/b/c");
         let path = PathBuf::from("a/b/c/img/2009/01/07/foo/file.jpg");
-        let aux = auxiliary_subpath(&root, &path, ty, img_dir, vid_dir);
+        let aux =
+            auxiliary_subpath(&root, &path, ty, img_dir, vid_dir, &template);
         assert_eq!(None, aux);
 
         // Relative root:
         let root = PathBuf::from("a/b/c");
         let path = PathBuf::from("a/b/c/img/2009/01/07/foo/file.jpg");
-        let aux = auxiliary_subpath(&root, &path, ty, img_dir, vid_dir);
+        let aux =
+            auxiliary_subpath(&root, &path, ty, img_dir, vid_dir, &template);
         assert_eq!(Some(PathBuf::from("foo")), aux);
 
         // Root mismatch:
         let root = PathBuf::from("a/b/c");
         let path = PathBuf::from("/a/b/c/img/2009/01/07/foo/file.jpg");
-        let aux = auxiliary_subpath(&root, &path, ty, img_dir, vid_dir);
+        let aux =
+            auxiliary_subpath(&root, &path, ty, img_dir, vid_dir, &template);
         assert_eq!(None, aux);
 
         // Root mismatch:
         let root = PathBuf::from("/meh");
         let path = PathBuf::from("/a/b/c/img/2009/01/07/foo/file.jpg");
-        let aux = auxiliary_subpath(&root, &path, ty, img_dir, vid_dir);
+        let aux =
+            auxiliary_subpath(&root, &path, ty, img_dir, vid_dir, &template);
         assert_eq!(None, aux);
     }
+
+    #[test]
+    fn t_parse_dir_name() {
+        assert_eq!("unsorted", parse_dir_name("unsorted").unwrap());
+        assert_eq!("unsorted", parse_dir_name("unsorted/").unwrap());
+        assert!(parse_dir_name("../x").is_err());
+        assert!(parse_dir_name("/tmp").is_err());
+        assert!(parse_dir_name("..").is_err());
+        assert!(parse_dir_name("a/b").is_err());
+        assert!(parse_dir_name("").is_err());
+    }
+
+    #[test]
+    fn t_write_atomically() {
+        let dir = tempfile::tempdir().unwrap();
+        let dst = dir.path().join("dst");
+
+        // Partial write, then failure.
+        let result = write_atomically(&dst, |tmp| {
+            fs::write(tmp, b"partial")?;
+            Err(io::Error::other("disk full"))
+        });
+        assert!(result.is_err());
+        assert_eq!(0, fs::read_dir(dir.path()).unwrap().count());
+
+        write_atomically(&dst, |tmp| fs::write(tmp, b"whole")).unwrap();
+        assert_eq!(b"whole", &fs::read(&dst).unwrap()[..]);
+        assert_eq!(1, fs::read_dir(dir.path()).unwrap().count());
+    }
+
+    #[test]
+    fn t_write_atomically_checked() {
+        let dir = tempfile::tempdir().unwrap();
+        let dst = dir.path().join("dst");
+
+        let mut checked = None;
+        let result = write_atomically_checked(
+            &dst,
+            |tmp| fs::write(tmp, b"corrupt"),
+            |tmp| {
+                checked = Some(fs::read(tmp)?);
+                anyhow::bail!("Digest mismatch")
+            },
+        );
+        assert_eq!(Some(&b"corrupt"[..]), checked.as_deref());
+        assert_eq!("Digest mismatch", result.unwrap_err().to_string());
+        assert!(!dst.exists());
+        assert_eq!(0, fs::read_dir(dir.path()).unwrap().count());
+    }
+
+    #[test]
+    fn t_index_find_elsewhere() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("src.jpg");
+        let dst = dir.path().join("dst");
+        fs::create_dir(&dst).unwrap();
+        // A well-known crc32 collision of the same length.
+        fs::write(&src, b"plumless").unwrap();
+        let digest = Hash::Crc32.digest(&src, Reading::default()).unwrap();
+        let other = dst.join(format!("a--crc32:{}.jpg", digest));
+        fs::write(&other, b"buckeroo").unwrap();
+        assert_eq!(
+            digest,
+            Hash::Crc32.digest(&other, Reading::default()).unwrap()
+        );
+        let new_dst = dst.join("new.jpg");
+
+        let index = Index::build(&dst, &Hash::Crc32);
+        assert_eq!(None, index.find_elsewhere(&digest, &src, &new_dst));
+
+        let same = dst.join(format!("b--crc32:{}.jpg", digest));
+        fs::write(&same, b"plumless").unwrap();
+        let index = Index::build(&dst, &Hash::Crc32);
+        assert_eq!(
+            Some(same.as_path()),
+            index.find_elsewhere(&digest, &src, &new_dst)
+        );
+        assert_eq!(None, index.find_elsewhere(&digest, &src, &same));
+    }
+
+    #[test]
+    fn t_move_across_devices() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("src");
+        let dst = dir.path().join("dst");
+        fs::write(&src, b"foo").unwrap();
+        let reading = Reading::default();
+        let digest = Hash::Crc32.digest(&src, reading).unwrap();
+
+        // The copy does not match the digest, so src must stay intact.
+        let result =
+            move_across_devices(&src, &dst, &Hash::Crc32, reading, "0");
+        assert!(result.is_err());
+        assert_eq!(b"foo", &fs::read(&src).unwrap()[..]);
+        assert!(!dst.exists());
+        assert_eq!(1, fs::read_dir(dir.path()).unwrap().count());
+
+        move_across_devices(&src, &dst, &Hash::Crc32, reading, &digest)
+            .unwrap();
+        assert!(!src.exists());
+        assert_eq!(b"foo", &fs::read(&dst).unwrap()[..]);
+        assert_eq!(1, fs::read_dir(dir.path()).unwrap().count());
+    }
+
+    /// TIFF with the given original and digitized dates.
+    fn exif_tiff(original: &str, digitized: &str) -> Vec<u8> {
+        use exif::{experimental::Writer, Field, In, Tag, Value};
+
+        let field = |tag, value: &str| Field {
+            tag,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![value.as_bytes().to_vec()]),
+        };
+        let original = field(Tag::DateTimeOriginal, original);
+        let digitized = field(Tag::DateTimeDigitized, digitized);
+        let mut writer = Writer::new();
+        writer.push_field(&original);
+        writer.push_field(&digitized);
+        let mut buf = io::Cursor::new(Vec::new());
+        writer.write(&mut buf, false).unwrap();
+        let mut tiff = buf.into_inner();
+        // nom-exif fails on input shorter than what it reads upfront.
+        tiff.resize(4096, 0);
+        tiff
+    }
+
+    /// MP4 with nothing but the mvhd creation time, in seconds since 1904.
+    fn mp4(creation_time: u32) -> Vec<u8> {
+        let bbox = |typ: &[u8], body: &[u8]| {
+            let size = u32::try_from(8 + body.len()).unwrap();
+            [&size.to_be_bytes()[..], typ, body].concat()
+        };
+        let mvhd = [
+            &[0; 4][..], // Version and flags.
+            &creation_time.to_be_bytes(),
+            &creation_time.to_be_bytes(),
+            &1000u32.to_be_bytes(), // Time scale.
+            &0u32.to_be_bytes(),    // Duration.
+            &[0; 76],
+            &2u32.to_be_bytes(), // Next track ID.
+        ]
+        .concat();
+        [
+            bbox(b"ftyp", b"isom\0\0\0\0isom"),
+            bbox(b"moov", &bbox(b"mvhd", &mvhd)),
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn t_merge_meta() {
+        use DateTag::*;
+
+        let t = Timestamp::from(chrono::NaiveDateTime::default());
+        let meta = |tag: Option<DateTag>, model: Option<&str>| Meta {
+            timestamp: tag.map(|_| (t, TimestampSource::Native)),
+            tag,
+            model: model.map(String::from),
+        };
+        let merge = |native, tags: &[DateTag], exiftool: Meta| {
+            let mut consulted = false;
+            let merged = merge_meta(native, EXIF_DATE_TAGS, tags, || {
+                consulted = true;
+                Some(exiftool)
+            });
+            (consulted, merged.tag, merged.model)
+        };
+
+        // Nothing of higher priority that we can't read ourselves.
+        let tags = [DateTimeOriginal, CreateDate, TrackCreateDate];
+        assert_eq!(
+            (false, Some(CreateDate), None),
+            merge(
+                meta(Some(CreateDate), None),
+                &tags,
+                meta(Some(DateTimeOriginal), Some("Foo"))
+            )
+        );
+
+        // Outranked by a tag we can't read ourselves, if exiftool finds it.
+        let tags = [TrackCreateDate, DateTimeOriginal, CreateDate];
+        assert_eq!(
+            (true, Some(TrackCreateDate), Some("Foo".into())),
+            merge(
+                meta(Some(CreateDate), Some("Foo")),
+                &tags,
+                meta(Some(TrackCreateDate), None)
+            )
+        );
+        assert_eq!(
+            (true, Some(DateTimeOriginal), Some("Bar".into())),
+            merge(
+                meta(Some(DateTimeOriginal), None),
+                &tags,
+                meta(Some(CreateDate), Some("Bar"))
+            )
+        );
+        assert_eq!(
+            (true, Some(CreateDate), None),
+            merge(meta(Some(CreateDate), None), &tags, meta(None, None))
+        );
+
+        // Nothing found natively.
+        assert_eq!(
+            (true, Some(CreateDate), None),
+            merge(meta(None, None), &tags, meta(Some(CreateDate), None))
+        );
+    }
+
+    #[test]
+    fn t_read_meta_native_tag_priority() {
+        use DateTag::*;
+
+        let found = |(meta, _): (Meta, _)| {
+            meta.tag.zip(meta.timestamp.map(|(t, _)| t.to_string()))
+        };
+        let original = Some((DateTimeOriginal, "2022-10-03T17:52:16".into()));
+        let digitized = Some((CreateDate, "2021-01-02T03:04:05".into()));
+        let tiff = exif_tiff("2022:10:03 17:52:16", "2021:01:02 03:04:05");
+        for read in [
+            |r: io::Cursor<&[u8]>, tags: &[DateTag]| read_meta_img(r, tags),
+            |r: io::Cursor<&[u8]>, tags: &[DateTag]| read_meta_vid(r, tags),
+        ] {
+            let read = |tags: &[DateTag]| {
+                let (meta, readable) = read(io::Cursor::new(&tiff[..]), tags);
+                assert_eq!(EXIF_DATE_TAGS, readable);
+                found((meta, readable))
+            };
+            assert_eq!(original, read(&[DateTimeOriginal, CreateDate]));
+            assert_eq!(digitized, read(&[CreateDate, DateTimeOriginal]));
+            assert_eq!(digitized, read(&[DateCreated, CreateDate]));
+            assert_eq!(None, read(&[DateCreated, TrackCreateDate]));
+        }
+
+        let mp4 = mp4(3_747_664_336);
+        let read = |tags: &[DateTag]| {
+            let (meta, readable) = read_meta_vid(io::Cursor::new(&mp4), tags);
+            assert_eq!(TRACK_DATE_TAGS, readable);
+            meta.tag
+        };
+        assert_eq!(
+            Some(TrackCreateDate),
+            read(&[DateTimeOriginal, TrackCreateDate, CreateDate])
+        );
+        assert_eq!(Some(CreationDate), read(DATE_TAGS));
+        assert_eq!(None, read(&[DateTimeOriginal]));
+
+        let (meta, readable) =
+            read_meta_img(io::Cursor::new(b"nope"), DATE_TAGS);
+        assert!(meta.timestamp.is_none() && readable.is_empty());
+    }
+
+    #[test]
+    fn t_read_meta_vid_utc() {
+        let utc =
+            chrono::DateTime::parse_from_rfc3339("2022-10-03T17:52:16Z")
+                .unwrap();
+        let since_1904 = utc.timestamp() + 2_082_844_800; // 1904 to 1970.
+        let mp4 = mp4(u32::try_from(since_1904).unwrap());
+        let (meta, _) = read_meta_vid(io::Cursor::new(&mp4), DATE_TAGS);
+        let (vid, _) = meta.timestamp.unwrap();
+        assert_eq!("2022-10-03T17:52:16Z", vid.to_string());
+        let tokyo = chrono::FixedOffset::east_opt(9 * 3600).unwrap();
+        for assumed in [AssumedZone::Local, AssumedZone::Fixed(tokyo)] {
+            assert_eq!(
+                "2022-10-04 02:52:16",
+                vid.in_zone(Zone::Fixed(tokyo), assumed).to_string()
+            );
+        }
+
+        // Same path time as a photo of the same instant, by a camera set
+        // to this machine's zone, under the default zones.
+        let local = utc.with_timezone(&chrono::Local).naive_local();
+        let tiff = exif_tiff(
+            &local.format("%Y:%m:%d %H:%M:%S").to_string(),
+            "2021:01:02 03:04:05",
+        );
+        let (meta, _) = read_meta_img(io::Cursor::new(&tiff), DATE_TAGS);
+        let (img, _) = meta.timestamp.unwrap();
+        let (zone, assumed) = (Zone::default(), AssumedZone::default());
+        assert_eq!(local, img.in_zone(zone, assumed));
+        assert_eq!(local, vid.in_zone(zone, assumed));
+        assert_eq!(
+            "2022-10-04 02:52:16",
+            vid.in_zone(zone, AssumedZone::Fixed(tokyo)).to_string()
+        );
+    }
 }
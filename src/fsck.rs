@@ -0,0 +1,107 @@
+use std::path::{Path, PathBuf};
+
+use rayon::prelude::*;
+
+use crate::{
+    files::{self, FilePaths},
+    hash::{Hasher, Reading},
+    manifest,
+};
+
+#[derive(Debug)]
+pub enum Problem {
+    /// Leftover temporary file from an interrupted write.
+    Incomplete,
+
+    /// Name does not contain a recognizable `<hash name>:<digest>`.
+    Unrecognized,
+
+    /// Could not be read and hashed.
+    Unreadable(String),
+
+    /// Content does not match the digest in the name (bitrot or truncation).
+    Corrupted { expected: String, actual: String },
+}
+
+impl Problem {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Incomplete => "incomplete",
+            Self::Unrecognized => "unrecognized",
+            Self::Unreadable(_) => "unreadable",
+            Self::Corrupted { .. } => "corrupted",
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub ok: usize,
+    pub problems: Vec<(PathBuf, Problem)>,
+}
+
+/// Check the files under `dst_root` against the digests in their names.
+#[tracing::instrument(level = "error", skip_all)]
+pub fn check(
+    dst_root: &Path,
+    reading: Reading,
+    show_progress: bool,
+) -> anyhow::Result<Report> {
+    tracing::info!(?dst_root, "Starting");
+    let dst_root = files::canonicalize(dst_root, "dst")?;
+    let progress_bar = files::progress_bar(show_progress)?;
+    let mut report = FilePaths::find(&dst_root)
+        .filter(|path| !manifest::is_internal(&dst_root, path))
+        .par_bridge()
+        .map(|path| {
+            progress_bar.inc_length(1);
+            let problem = check_file(&path, reading);
+            progress_bar.inc(1);
+            (path, problem)
+        })
+        .fold(Report::default, |mut report, (path, problem)| {
+            match problem {
+                None => report.ok += 1,
+                Some(problem) => report.problems.push((path, problem)),
+            }
+            report
+        })
+        .reduce(Report::default, |mut a, b| {
+            a.ok += b.ok;
+            a.problems.extend(b.problems);
+            a
+        });
+    progress_bar.finish();
+    report.problems.sort_by(|(a, _), (b, _)| a.cmp(b));
+    tracing::info!(
+        ok = report.ok,
+        problems = report.problems.len(),
+        "Finished"
+    );
+    Ok(report)
+}
+
+#[tracing::instrument(level = "error", skip(reading))]
+fn check_file(path: &Path, reading: Reading) -> Option<Problem> {
+    let name = path.file_name()?.to_string_lossy();
+    if name.starts_with(files::TMP_FILE_PREFIX) {
+        return Some(Problem::Incomplete);
+    }
+    let Some((
+        hash,
+        files::DigestTag {
+            digest: expected, ..
+        },
+    )) = files::find_digest(&name)
+    else {
+        return Some(Problem::Unrecognized);
+    };
+    match hash.digest(path, reading) {
+        Err(error) => Some(Problem::Unreadable(error.to_string())),
+        Ok(actual) if actual == expected => None,
+        Ok(actual) => Some(Problem::Corrupted {
+            expected: expected.to_string(),
+            actual,
+        }),
+    }
+}
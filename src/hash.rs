@@ -1,109 +1,335 @@
 use std::{
-    io::{self, Read},
+    fmt, fs,
+    io::{self, Read, Seek},
     path::Path,
 };
 
-#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+/// Default size of the buffer into which files are read for hashing.
+pub const BUF_SIZE: usize = 1024 * 1024;
+
+/// Digest algorithm, by whose digests organized files are named, indexed and
+/// verified. The built-in ones are the variants of [`enum@Hash`], but any
+/// other can be plugged into [`crate::files::organize`] by implementing this.
+pub trait Hasher: fmt::Debug + Sync {
+    type Digester: Digester;
+
+    /// Tag of the digests in file names, as in `<name>:<digest>`. Should be
+    /// ASCII alphanumeric, for the digests to be found in the names again.
+    fn name(&self) -> &str;
+
+    /// Length of the hex-encoded digest.
+    fn hex_len(&self) -> usize;
+
+    /// Start computing a digest incrementally.
+    fn digester(&self) -> Self::Digester;
+
+    /// Hex-encoded digest of the file at the path.
+    fn digest(&self, path: &Path, reading: Reading) -> io::Result<String> {
+        let mut digester = self.digester();
+        fs::File::open(path)
+            .and_then(|mut file| {
+                reading.feed(&mut file, |data| digester.update(data))
+            })
+            .map_err(|error| {
+                let algo = self.name();
+                tracing::error!(?path, algo, ?error, "Failed to hash file");
+                error
+            })?;
+        Ok(digester.finalize())
+    }
+}
+
+/// Digest being computed from data fed to it piece by piece.
+pub trait Digester: Send {
+    fn update(&mut self, data: &[u8]);
+
+    /// Hex-encoded digest.
+    fn finalize(self) -> String;
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
 pub enum Hash {
     Sha1,
     Sha256,
     Md5,
+    #[default]
     Crc32,
+    Blake3,
+    Xxh128,
 }
 
-impl Default for Hash {
-    fn default() -> Self {
-        Self::Crc32
+impl Hash {
+    pub fn from_name(name: &str) -> Option<Self> {
+        <Self as clap::ValueEnum>::value_variants()
+            .iter()
+            .find(|hash| hash.name() == name)
+            .copied()
     }
 }
 
-impl Hash {
-    pub fn name(&self) -> &'static str {
+impl Hasher for Hash {
+    type Digester = HashDigester;
+
+    fn name(&self) -> &str {
         match self {
             Self::Sha1 => "sha1",
             Self::Sha256 => "sha256",
             Self::Md5 => "md5",
             Self::Crc32 => "crc32",
+            Self::Blake3 => "blake3",
+            Self::Xxh128 => "xxh128",
+        }
+    }
+
+    fn hex_len(&self) -> usize {
+        match self {
+            Self::Sha1 => 40,
+            Self::Sha256 => 64,
+            Self::Md5 => 32,
+            Self::Crc32 => 8,
+            Self::Blake3 => 64,
+            Self::Xxh128 => 32,
         }
     }
 
-    pub fn digest(&self, path: &Path) -> io::Result<String> {
-        let mut file = std::fs::File::open(path)?;
+    fn digester(&self) -> HashDigester {
         match self {
-            Hash::Sha1 => digest_sha1(&mut file),
-            Hash::Sha256 => digest_sha256(&mut file),
-            Hash::Md5 => digest_md5(&mut file),
-            Hash::Crc32 => digest_crc32(&mut file),
+            Self::Sha1 => HashDigester::Sha1(sha1::Digest::new()),
+            Self::Sha256 => HashDigester::Sha256(sha2::Digest::new()),
+            Self::Md5 => HashDigester::Md5(md5::Digest::new()),
+            Self::Crc32 => HashDigester::Crc32(crc32fast::Hasher::new()),
+            Self::Blake3 => HashDigester::Blake3(Box::default()),
+            Self::Xxh128 => HashDigester::Xxh128(Box::default()),
         }
-        .map_err(|error| {
-            tracing::error!(?path, algo = ?self, ?error, "Failed to hash file");
-            error
-        })
     }
 }
 
-fn digest_sha1<R: Read>(data: &mut R) -> io::Result<String> {
-    use sha1::{Digest, Sha1};
+/// Digester of any of the built-in algorithms.
+pub enum HashDigester {
+    Sha1(sha1::Sha1),
+    Sha256(sha2::Sha256),
+    Md5(md5::Md5),
+    Crc32(crc32fast::Hasher),
+    Blake3(Box<blake3::Hasher>),
+    Xxh128(Box<xxhash_rust::xxh3::Xxh3>),
+}
 
-    let mut hash = Sha1::new();
-    let mut buff = [0; 1024];
-    loop {
-        let n = data.read(&mut buff)?;
-        if n == 0 {
-            break;
+impl Digester for HashDigester {
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Sha1(hash) => Digester::update(hash, data),
+            Self::Sha256(hash) => Digester::update(hash, data),
+            Self::Md5(hash) => Digester::update(hash, data),
+            Self::Crc32(hash) => Digester::update(hash, data),
+            Self::Blake3(hash) => Digester::update(hash, data),
+            Self::Xxh128(hash) => Digester::update(hash, data),
         }
-        hash.update(&buff[..n]);
     }
-    let digest = hash.finalize();
-    let hex = format!("{:x}", digest);
-    Ok(hex)
+
+    fn finalize(self) -> String {
+        match self {
+            Self::Sha1(hash) => Digester::finalize(hash),
+            Self::Sha256(hash) => Digester::finalize(hash),
+            Self::Md5(hash) => Digester::finalize(hash),
+            Self::Crc32(hash) => Digester::finalize(hash),
+            Self::Blake3(hash) => Digester::finalize(hash),
+            Self::Xxh128(hash) => Digester::finalize(hash),
+        }
+    }
+}
+
+impl<D: Digester> Digester for Box<D> {
+    fn update(&mut self, data: &[u8]) {
+        (**self).update(data);
+    }
+
+    fn finalize(self) -> String {
+        (*self).finalize()
+    }
+}
+
+impl Digester for sha1::Sha1 {
+    fn update(&mut self, data: &[u8]) {
+        sha1::Digest::update(self, data);
+    }
+
+    fn finalize(self) -> String {
+        format!("{:x}", sha1::Digest::finalize(self))
+    }
+}
+
+impl Digester for sha2::Sha256 {
+    fn update(&mut self, data: &[u8]) {
+        sha2::Digest::update(self, data);
+    }
+
+    fn finalize(self) -> String {
+        format!("{:x}", sha2::Digest::finalize(self))
+    }
+}
+
+impl Digester for md5::Md5 {
+    fn update(&mut self, data: &[u8]) {
+        md5::Digest::update(self, data);
+    }
+
+    fn finalize(self) -> String {
+        format!("{:x}", md5::Digest::finalize(self))
+    }
 }
 
-fn digest_sha256<R: Read>(data: &mut R) -> io::Result<String> {
-    use sha2::{Digest, Sha256};
+impl Digester for crc32fast::Hasher {
+    fn update(&mut self, data: &[u8]) {
+        crc32fast::Hasher::update(self, data);
+    }
+
+    fn finalize(self) -> String {
+        format!("{:08x}", crc32fast::Hasher::finalize(self))
+    }
+}
+
+/// Pieces of at least this size are hashed by BLAKE3 on multiple threads.
+/// Below it, the thread coordination costs more than it saves.
+const BLAKE3_RAYON_MIN: usize = 128 * 1024;
 
-    let mut hash = Sha256::new();
-    let mut buff = [0; 1024];
-    loop {
-        let n = data.read(&mut buff)?;
-        if n == 0 {
-            break;
+impl Digester for blake3::Hasher {
+    fn update(&mut self, data: &[u8]) {
+        if data.len() >= BLAKE3_RAYON_MIN {
+            self.update_rayon(data);
+        } else {
+            blake3::Hasher::update(self, data);
         }
-        hash.update(&buff[..n]);
     }
-    let digest = hash.finalize();
-    let hex = format!("{:x}", digest);
-    Ok(hex)
+
+    fn finalize(self) -> String {
+        blake3::Hasher::finalize(&self).to_hex().to_string()
+    }
+}
+
+impl Digester for xxhash_rust::xxh3::Xxh3 {
+    fn update(&mut self, data: &[u8]) {
+        xxhash_rust::xxh3::Xxh3::update(self, data);
+    }
+
+    fn finalize(self) -> String {
+        format!("{:032x}", self.digest128())
+    }
 }
 
-fn digest_md5<R: Read>(data: &mut R) -> io::Result<String> {
-    use md5::{Digest, Md5};
+/// How files are read for hashing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reading {
+    /// Size of the buffer into which the file is read, at most, per syscall.
+    pub buf_size: usize,
+
+    /// Map the file into memory instead of reading it. Saves the copying,
+    /// but is only meant for local files, since an I/O error on a mapped
+    /// file kills the process with SIGBUS, rather than being reported.
+    pub mmap: bool,
+}
+
+impl Default for Reading {
+    fn default() -> Self {
+        Self {
+            buf_size: BUF_SIZE,
+            mmap: false,
+        }
+    }
+}
 
-    let mut hash = Md5::new();
-    let mut buff = [0; 1024];
-    loop {
-        let n = data.read(&mut buff)?;
-        if n == 0 {
-            break;
+impl Reading {
+    /// Pass the rest of the file, from its current position, piece by
+    /// piece, to the update function, e.g. that of a digester.
+    pub fn feed<F: FnMut(&[u8])>(
+        &self,
+        file: &mut fs::File,
+        mut update: F,
+    ) -> io::Result<()> {
+        if self.mmap {
+            let pos = file.stream_position()?;
+            if pos >= file.metadata()?.len() {
+                // Nothing to hash and empty files cannot be mapped.
+                return Ok(());
+            }
+            // SAFETY: The map is only read, while we hold the file open. It
+            // is only as safe as files under hashing not being truncated
+            // concurrently, which is the documented tradeoff of the option.
+            let map = unsafe {
+                memmap2::MmapOptions::new().offset(pos).map(&*file)?
+            };
+            update(&map);
+            return Ok(());
+        }
+        let mut buf = vec![0; self.buf_size.max(1)];
+        loop {
+            match file.read(&mut buf) {
+                Ok(0) => return Ok(()),
+                Ok(n) => update(&buf[..n]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
         }
-        hash.update(&buff[..n]);
     }
-    let digest = hash.finalize();
-    let hex = format!("{:x}", digest);
-    Ok(hex)
 }
 
-fn digest_crc32<R: Read>(data: &mut R) -> io::Result<String> {
-    let mut hash = crc32fast::Hasher::new();
-    let mut buff = [0; 1024];
-    loop {
-        let n = data.read(&mut buff)?;
-        if n == 0 {
-            break;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_digester() {
+        for hash in <Hash as clap::ValueEnum>::value_variants() {
+            assert_eq!(Some(*hash), Hash::from_name(hash.name()));
+            assert_eq!(hash.hex_len(), hash.digester().finalize().len());
+        }
+        assert_eq!(
+            "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262",
+            Hash::Blake3.digester().finalize()
+        );
+        assert_eq!(
+            "99aa06d3014798d86001c324468d497f",
+            Hash::Xxh128.digester().finalize()
+        );
+
+        // Multi-threaded BLAKE3 agrees with the single-threaded one.
+        let data: Vec<u8> =
+            (0..4 * BLAKE3_RAYON_MIN).map(|i| (i % 251) as u8).collect();
+        let mut whole = Hash::Blake3.digester();
+        whole.update(&data);
+        let mut pieces = Hash::Blake3.digester();
+        for piece in data.chunks(1000) {
+            pieces.update(piece);
+        }
+        assert_eq!(whole.finalize(), pieces.finalize());
+    }
+
+    #[test]
+    fn t_digest() {
+        let path = Path::new("tests/data/src/foo.jpg");
+        let expected = {
+            let mut digester = Hash::Sha256.digester();
+            digester.update(&fs::read(path).unwrap());
+            digester.finalize()
+        };
+        for (buf_size, mmap) in
+            [(1, false), (7, false), (BUF_SIZE, false), (0, true)]
+        {
+            let reading = Reading { buf_size, mmap };
+            let actual = Hash::Sha256.digest(path, reading).unwrap();
+            assert_eq!(expected, actual, "{:?}", reading);
+        }
+        // From the middle.
+        let mut file = fs::File::open(path).unwrap();
+        let mut digester = Hash::Sha256.digester();
+        file.seek(io::SeekFrom::Start(100)).unwrap();
+        Reading {
+            buf_size: 64,
+            mmap: true,
         }
-        hash.update(&buff[..n]);
+        .feed(&mut file, |data| digester.update(data))
+        .unwrap();
+        let mut expected = Hash::Sha256.digester();
+        expected.update(&fs::read(path).unwrap()[100..]);
+        assert_eq!(expected.finalize(), digester.finalize());
     }
-    let digest = hash.finalize();
-    let hex = format!("{:08x}", digest);
-    Ok(hex)
 }
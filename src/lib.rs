@@ -1,7 +1,15 @@
 pub mod files;
+pub mod fsck;
 pub mod hash;
+pub mod manifest;
+pub mod rehash;
+pub mod template;
+pub mod time_shift;
+pub mod timestamp;
+pub mod undo;
 
 mod exiftool;
+mod source;
 
 pub fn tracing_init(level: Option<tracing::Level>) -> anyhow::Result<()> {
     use tracing_subscriber::{fmt, layer::SubscriberExt, EnvFilter, Layer};
@@ -1,14 +1,103 @@
-use std::path::PathBuf;
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
 
 use clap::Parser;
 
+/// Exit code for when the command ran to completion, but some files had
+/// problems.
+const EXIT_PROBLEMS: u8 = 2;
+
 #[derive(Debug, Parser)]
 #[command(version, about)]
 struct Cli {
     /// Specify log level, if any.
-    #[clap(short, long = "log", default_value_t = tracing::Level::WARN)]
+    #[clap(
+        short,
+        long = "log",
+        default_value_t = tracing::Level::WARN,
+        global = true
+    )]
     log_level: tracing::Level,
 
+    /// Show progress bar (when copying, moving or verifying, but never when
+    /// showing).
+    /// NOTE: May conflict with logging output, so may need to set the log
+    /// level to error to avoid screen noise.
+    #[clap(
+        short = 'p',
+        long = "progress",
+        default_value_t = false,
+        global = true
+    )]
+    show_progress: bool,
+
+    /// Size of the buffer into which files are read when hashing them.
+    /// Accepts K, M and G suffixes (powers of 1024).
+    #[clap(
+        long,
+        value_name = "SIZE",
+        default_value = "1M",
+        value_parser = parse_size,
+        global = true
+    )]
+    hash_buffer_size: usize,
+
+    /// Memory-map files when hashing them, instead of reading them into a
+    /// buffer. Only for local files: an I/O error on a mapped file kills
+    /// the process, rather than being reported.
+    #[clap(long, default_value_t = false, global = true)]
+    mmap: bool,
+
+    #[clap(subcommand)]
+    cmd: Cmd,
+}
+
+#[derive(Debug, clap::Subcommand)]
+enum Cmd {
+    /// Organize photo/video files from SRC_ROOT into DST_ROOT. The default
+    /// command, i.e. `phorg [OPTIONS] SRC_ROOT DST_ROOT OP` works as well.
+    Organize(Box<Organize>),
+
+    /// Check the files in DST_ROOT against the digests in their names.
+    #[clap(visible_alias = "fsck")]
+    Verify {
+        /// Directory structure previously created by organize.
+        dst_root: PathBuf,
+    },
+
+    /// Reverse an organize run: move moved files back to their original
+    /// location and delete the copied/linked ones, unless they were modified
+    /// since.
+    Undo {
+        /// Directory structure previously created by organize.
+        dst_root: PathBuf,
+
+        /// ID of the organize run, as printed by it and recorded in
+        /// DST_ROOT/.phorg/manifest.jsonl
+        run_id: String,
+    },
+
+    /// Rename the files in DST_ROOT, in place, to carry digests of another
+    /// hash, after checking them against their current ones.
+    Rehash {
+        /// Directory structure previously created by organize.
+        dst_root: PathBuf,
+
+        /// Hash to rename to.
+        #[clap(long, value_enum)]
+        hash: phorg::hash::Hash,
+
+        /// Only show what would be renamed.
+        #[clap(short = 'n', long, default_value_t = false)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Debug, clap::Args)]
+struct Organize {
     /// Hash.
     #[clap(long, value_enum, default_value_t = phorg::hash::Hash::default())]
     hash: phorg::hash::Hash,
@@ -17,15 +106,82 @@ struct Cli {
     #[clap(short = 'f', long = "force", default_value_t = false)]
     force: bool,
 
+    /// Re-hash copied files after writing them and discard the ones whose
+    /// digest does not match the src (cross-device moves are always verified).
+    #[clap(long, default_value_t = false)]
+    verify: bool,
+
+    /// Instead of skipping the files for which no timestamp could be found,
+    /// put them into this subdirectory under DST_ROOT, named by their digest
+    /// followed by their original name.
+    #[clap(long, value_parser = phorg::files::parse_dir_name)]
+    unsorted_dir: Option<String>,
+
+    /// Don't skip files whose content is already somewhere in DST_ROOT
+    /// (e.g. moved into a semantic subdirectory), just check the exact
+    /// destination path.
+    #[clap(long, default_value_t = false)]
+    no_index: bool,
+
     /// Don't try falling back on exiftool if we fail to extract Exif data directly.
     #[clap(long, default_value_t = false)]
     no_exiftool: bool,
 
-    /// Show progress bar (when copying or moving, but never when showing).
-    /// NOTE: May conflict with logging output, so may need to set the log
-    /// level to error to avoid screen noise.
-    #[clap(short = 'p', long = "progress", default_value_t = false)]
-    show_progress: bool,
+    /// Additional chrono format of the date and time in file names, tried
+    /// before the built-in ones when no timestamp is found in metadata,
+    /// e.g. "%d.%m.%Y %H%M", "IMG_%Y%m%d", or "%Y%m%d" for bare dates,
+    /// which are not recognized by default. May be given multiple times.
+    #[clap(long = "name-time-pattern", name = "PATTERN")]
+    name_time_patterns: Vec<String>,
+
+    /// Don't look for the date and time in file names.
+    #[clap(long, default_value_t = false)]
+    no_name_time: bool,
+
+    /// When no timestamp can be found in metadata, use the file's birth time
+    /// or, if that is unavailable, its modification time.
+    #[clap(long, default_value_t = false)]
+    fs_time: bool,
+
+    /// Correct a wrong camera clock by shifting all timestamps, e.g. "+9h"
+    /// or "-1d 3m" (i.e. back by 1 day and 3 minutes). Units: d, h, m, s.
+    #[clap(long, allow_hyphen_values = true)]
+    time_shift: Option<phorg::time_shift::TimeShift>,
+
+    /// Like --time-shift, but only for the given camera model (as recorded
+    /// in Exif), overriding --time-shift, e.g. "Canon EOS 5D=+9h".
+    /// May be given multiple times.
+    #[clap(long = "model-time-shift", name = "MODEL=SHIFT")]
+    model_time_shifts: Vec<phorg::time_shift::ModelTimeShift>,
+
+    /// Zone of the times in destination paths: "capture" for the wall-clock
+    /// time where the photo/video was taken, or a fixed offset from UTC,
+    /// e.g. "+09:00". Files whose capture offset is unknown are always
+    /// placed by their wall-clock time.
+    #[clap(long, default_value = "capture", allow_hyphen_values = true)]
+    timezone: phorg::timestamp::Zone,
+
+    /// Capture zone assumed for the times recorded in UTC only, such as
+    /// those of most non-Apple videos, when placing by capture time:
+    /// "local" for this machine's zone, or a fixed offset from UTC.
+    #[clap(long, default_value = "local", allow_hyphen_values = true)]
+    assumed_timezone: phorg::timestamp::AssumedZone,
+
+    /// Comma-separated metadata tags to read the timestamp from, in order of
+    /// priority [default: DateTimeOriginal,DateTimeCreated,CreationDate,
+    /// CreateDate,DateCreated,Datecreate,TrackCreateDate]
+    #[clap(long, value_enum, value_delimiter = ',')]
+    date_tags: Vec<phorg::files::DateTag>,
+
+    /// The exiftool executable to fall back on.
+    #[clap(long, default_value = "exiftool")]
+    exiftool: PathBuf,
+
+    /// Additional argument to pass to exiftool with every file, e.g.
+    /// --exiftool-arg=-api --exiftool-arg=largefilesupport=1 (for videos
+    /// over 4 GB). May be given multiple times.
+    #[clap(long = "exiftool-arg", name = "ARG", allow_hyphen_values = true)]
+    exiftool_args: Vec<String>,
 
     /// Process only this file type, otherwise all supported will be processed.
     #[clap(short, long = "type", name = "TYPE", value_enum)]
@@ -39,6 +195,21 @@ struct Cli {
     #[clap(long, default_value = "vid")]
     vid_dir: String,
 
+    /// Layout of paths under the img/vid subdirectories. Placeholders:
+    /// {year}, {month}, {day}, {hour}, {minute}, {second}, {subsec}
+    /// (milliseconds, e.g. to order bursts), {date}, {time}, {hash},
+    /// {model}, {ext}. The last path component is the file name,
+    /// which must contain {hash}.
+    #[clap(long, default_value = phorg::template::DEFAULT)]
+    template: phorg::template::Template,
+
+    /// Avoid characters in names which are illegal on FAT/exFAT and SMB
+    /// shares, i.e. use "17-52-16" and "crc32-75daa68d" instead of
+    /// "17:52:16" and "crc32:75daa68d". Both forms are recognized when
+    /// reading previously organized files.
+    #[clap(long, default_value_t = false)]
+    portable_names: bool,
+
     /// Where to look for photo/video files.
     src_root: PathBuf,
 
@@ -50,24 +221,233 @@ struct Cli {
     op: phorg::files::Op,
 }
 
-fn main() -> anyhow::Result<()> {
+fn main() -> anyhow::Result<ExitCode> {
     human_panic_setup();
-    let cli = Cli::parse();
+    let cli = parse_cli(std::env::args_os().collect());
     phorg::tracing_init(Some(cli.log_level))?;
-    let use_exiftool = !cli.no_exiftool;
-    phorg::files::organize(
-        &cli.src_root,
-        &cli.dst_root,
-        &cli.op,
-        &cli.img_dir,
-        &cli.vid_dir,
-        cli.typ,
-        cli.force,
-        use_exiftool,
-        cli.show_progress,
-        cli.hash,
-    )?;
-    Ok(())
+    let reading = phorg::hash::Reading {
+        buf_size: cli.hash_buffer_size,
+        mmap: cli.mmap,
+    };
+    match cli.cmd {
+        Cmd::Organize(cmd) => {
+            let opts = phorg::files::Options {
+                img_dir: cmd.img_dir,
+                vid_dir: cmd.vid_dir,
+                template: cmd.template,
+                portable_names: cmd.portable_names,
+                unsorted_dir: cmd.unsorted_dir,
+                ty_filter: cmd.typ,
+                force: cmd.force,
+                use_exiftool: !cmd.no_exiftool,
+                exiftool_exe: cmd.exiftool,
+                exiftool_args: cmd.exiftool_args,
+                date_tags: if cmd.date_tags.is_empty() {
+                    phorg::files::DATE_TAGS.to_vec()
+                } else {
+                    cmd.date_tags
+                },
+                name_time_patterns: if cmd.no_name_time {
+                    Vec::new()
+                } else {
+                    cmd.name_time_patterns
+                        .into_iter()
+                        .chain(
+                            phorg::files::NAME_TIME_PATTERNS
+                                .iter()
+                                .map(|p| p.to_string()),
+                        )
+                        .collect()
+                },
+                use_fs_time: cmd.fs_time,
+                zone: cmd.timezone,
+                assumed_zone: cmd.assumed_timezone,
+                time_shifts: phorg::time_shift::TimeShifts {
+                    default: cmd.time_shift.unwrap_or_default(),
+                    by_model: cmd
+                        .model_time_shifts
+                        .into_iter()
+                        .map(|m| (m.model, m.shift))
+                        .collect(),
+                },
+                use_index: !cmd.no_index,
+                show_progress: cli.show_progress,
+                hash: cmd.hash,
+                reading,
+                verify: cmd.verify,
+            };
+            let report = phorg::files::organize(
+                &cmd.src_root,
+                &cmd.dst_root,
+                &cmd.op,
+                &opts,
+            )?;
+            if !matches!(cmd.op, phorg::files::Op::Show) {
+                eprintln!("Run ID: {}", report.run_id);
+            }
+            for (count, label) in [
+                (report.shown, "shown"),
+                (report.organized, "organized"),
+                (report.unsorted, "unsorted: no timestamp"),
+                (report.present, "already present"),
+                (report.skipped_filtered, "skipped: filtered out by type"),
+                (report.skipped_unsupported, "skipped: unsupported type"),
+                (report.skipped_no_timestamp, "skipped: no timestamp"),
+                (report.hash_errors, "failed: hash error"),
+                (report.io_errors, "failed: I/O error"),
+                (report.unrecorded, "failed: not recorded in manifest"),
+            ] {
+                if count > 0 {
+                    eprintln!("{:>7} {}", count, label);
+                }
+            }
+            if report.failures() == 0 {
+                Ok(ExitCode::SUCCESS)
+            } else {
+                Ok(ExitCode::from(EXIT_PROBLEMS))
+            }
+        }
+        Cmd::Verify { dst_root } => {
+            let report =
+                phorg::fsck::check(&dst_root, reading, cli.show_progress)?;
+            for (path, problem) in &report.problems {
+                print_fsck_problem(path, problem);
+            }
+            eprintln!("{} ok, {} problems", report.ok, report.problems.len());
+            if report.problems.is_empty() {
+                Ok(ExitCode::SUCCESS)
+            } else {
+                Ok(ExitCode::from(EXIT_PROBLEMS))
+            }
+        }
+        Cmd::Undo { dst_root, run_id } => {
+            // Any of the built-in hashes, which are all resolved by name.
+            let hash = phorg::hash::Hash::default();
+            let report =
+                phorg::undo::undo(&dst_root, &run_id, &hash, reading)?;
+            for (path, reason) in &report.refused {
+                println!("refused {:?} {}", path, reason);
+            }
+            eprintln!(
+                "{} undone, {} refused",
+                report.undone,
+                report.refused.len()
+            );
+            if report.refused.is_empty() {
+                Ok(ExitCode::SUCCESS)
+            } else {
+                Ok(ExitCode::from(EXIT_PROBLEMS))
+            }
+        }
+        Cmd::Rehash {
+            dst_root,
+            hash,
+            dry_run,
+        } => {
+            let report = phorg::rehash::rehash(
+                &dst_root,
+                &hash,
+                reading,
+                dry_run,
+                cli.show_progress,
+            )?;
+            for (old, new) in &report.renamed {
+                println!("{:?} --> {:?}", old, new);
+            }
+            for (path, problem) in &report.problems {
+                match problem {
+                    phorg::rehash::Problem::Unverified(problem) => {
+                        print_fsck_problem(path, problem);
+                    }
+                    phorg::rehash::Problem::Conflict(other) => {
+                        println!("{} {:?} {:?}", problem.name(), path, other);
+                    }
+                    phorg::rehash::Problem::Unrenamed(error) => {
+                        println!("{} {:?} {}", problem.name(), path, error);
+                    }
+                }
+            }
+            eprintln!(
+                "{} {}, {} current, {} problems",
+                report.renamed.len(),
+                if dry_run { "to rename" } else { "renamed" },
+                report.current,
+                report.problems.len()
+            );
+            if report.problems.is_empty() {
+                Ok(ExitCode::SUCCESS)
+            } else {
+                Ok(ExitCode::from(EXIT_PROBLEMS))
+            }
+        }
+    }
+}
+
+/// Parse the command line, taking organize to be the command when none is
+/// given, as in `phorg SRC_ROOT DST_ROOT OP`, which predates the others.
+fn parse_cli(args: Vec<OsString>) -> Cli {
+    use clap::error::ErrorKind;
+
+    Cli::try_parse_from(&args).unwrap_or_else(|error| {
+        let kind = error.kind();
+        if !matches!(
+            kind,
+            ErrorKind::InvalidSubcommand | ErrorKind::UnknownArgument
+        ) {
+            error.exit();
+        }
+        let mut args = args;
+        args.insert(args.len().min(1), "organize".into());
+        Cli::try_parse_from(args).unwrap_or_else(|organize_error| {
+            // Report the error of the intended command.
+            if kind == ErrorKind::InvalidSubcommand {
+                organize_error.exit()
+            } else {
+                error.exit()
+            }
+        })
+    })
+}
+
+fn print_fsck_problem(path: &Path, problem: &phorg::fsck::Problem) {
+    match problem {
+        phorg::fsck::Problem::Corrupted { expected, actual } => {
+            println!(
+                "{} {:?} expected={} actual={}",
+                problem.name(),
+                path,
+                expected,
+                actual
+            );
+        }
+        phorg::fsck::Problem::Unreadable(error) => {
+            println!("{} {:?} {}", problem.name(), path, error);
+        }
+        _ => {
+            println!("{} {:?}", problem.name(), path);
+        }
+    }
+}
+
+/// Parse a positive number of bytes, optionally suffixed with K, M or G.
+fn parse_size(s: &str) -> anyhow::Result<usize> {
+    let (num, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => s.split_at(i),
+        None => (s, ""),
+    };
+    let unit: usize = match unit.to_ascii_uppercase().as_str() {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        _ => {
+            anyhow::bail!("Unknown unit {:?}, expected one of: K, M, G", unit)
+        }
+    };
+    num.parse::<usize>()?
+        .checked_mul(unit)
+        .filter(|size| *size > 0)
+        .ok_or_else(|| anyhow::anyhow!("Size out of range: {:?}", s))
 }
 
 fn human_panic_setup() {
@@ -84,3 +464,36 @@ fn human_panic_setup() {
     .homepage(repo!())
     .support(concat!("- Submit an issue at ", repo!(), "/issues")));
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_date_tags() {
+        use phorg::files::DateTag::*;
+
+        let date_tags = |args: &[&str]| -> clap::error::Result<_> {
+            let args = ["phorg", "organize"]
+                .iter()
+                .chain(args)
+                .chain(&["src", "dst", "copy"]);
+            match Cli::try_parse_from(args)?.cmd {
+                Cmd::Organize(cmd) => Ok(cmd.date_tags),
+                _ => panic!("Not organize."),
+            }
+        };
+        assert_eq!(
+            vec![TrackCreateDate, DateTimeOriginal],
+            date_tags(&["--date-tags", "TrackCreateDate,DateTimeOriginal"])
+                .unwrap()
+        );
+        assert_eq!(
+            vec![CreateDate, Datecreate],
+            date_tags(&["--date-tags=CreateDate", "--date-tags=Datecreate"])
+                .unwrap()
+        );
+        assert!(date_tags(&[]).unwrap().is_empty());
+        assert!(date_tags(&["--date-tags", "Nope"]).is_err());
+    }
+}
@@ -0,0 +1,133 @@
+//! Append-only record of what organize did, as JSON Lines in dst.
+
+use std::{
+    fs,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::Context;
+
+use crate::{
+    files::{Op, Outcome, TimestampSource, Typ},
+    timestamp::Timestamp,
+};
+
+/// Directory, under dst root, for phorg's own files.
+pub const DIR: &str = ".phorg";
+
+const FILE: &str = "manifest.jsonl";
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct Entry {
+    pub run_id: String,
+
+    /// When the entry was recorded, in RFC 3339.
+    pub time: String,
+
+    pub src: PathBuf,
+
+    /// Relative to dst root.
+    pub dst: PathBuf,
+
+    pub typ: Typ,
+    /// None if unsorted.
+    pub timestamp: Option<Timestamp>,
+
+    pub timestamp_source: Option<TimestampSource>,
+    pub hash: String,
+    pub digest: String,
+    pub op: Op,
+    pub outcome: Outcome,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+pub fn path(dst_root: &Path) -> PathBuf {
+    dst_root.join(DIR).join(FILE)
+}
+
+/// Is the path one of phorg's own files, rather than an organized one?
+pub fn is_internal(dst_root: &Path, path: &Path) -> bool {
+    path.starts_with(dst_root.join(DIR))
+}
+
+/// Unique enough to identify an organize run.
+pub fn new_run_id() -> String {
+    format!(
+        "{}-{}",
+        chrono::Local::now().format("%Y%m%dT%H%M%S"),
+        std::process::id()
+    )
+}
+
+pub struct Writer {
+    file: Mutex<fs::File>,
+}
+
+impl Writer {
+    pub fn open(dst_root: &Path) -> anyhow::Result<Self> {
+        let path = path(dst_root);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).context(format!(
+                "Failed to create manifest dir: {:?}",
+                dir
+            ))?;
+        }
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .context(format!("Failed to open manifest: {:?}", path))?;
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn append(&self, entry: &Entry) -> anyhow::Result<()> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+        // A single write per line keeps lines whole in an append-mode file.
+        let mut file = self
+            .file
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Manifest lock poisoned"))?;
+        file.write_all(line.as_bytes())
+            .context("Failed to write manifest entry")?;
+        Ok(())
+    }
+}
+
+/// Read all entries, skipping (and logging) the lines which fail to parse.
+pub fn read(dst_root: &Path) -> anyhow::Result<Vec<Entry>> {
+    let path = path(dst_root);
+    let file = match fs::File::open(&path) {
+        Ok(file) => file,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {
+            return Ok(Vec::new());
+        }
+        Err(error) => {
+            return Err(error)
+                .context(format!("Failed to open manifest: {:?}", path));
+        }
+    };
+    let mut entries = Vec::new();
+    for (i, line_result) in io::BufReader::new(file).lines().enumerate() {
+        let line = line_result
+            .context(format!("Failed to read manifest: {:?}", path))?;
+        match serde_json::from_str::<Entry>(&line) {
+            Ok(entry) => entries.push(entry),
+            Err(error) => {
+                tracing::error!(
+                    ?path,
+                    line = i + 1,
+                    ?error,
+                    "Failed to parse manifest entry"
+                );
+            }
+        }
+    }
+    Ok(entries)
+}
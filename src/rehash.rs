@@ -0,0 +1,252 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use rayon::prelude::*;
+
+use crate::{
+    files::{self, FilePaths, Outcome},
+    fsck,
+    hash::{Digester, Hasher, Reading},
+    manifest, template,
+};
+
+#[derive(Debug)]
+pub enum Problem {
+    /// Failed the check against the digest in its name, so it is left
+    /// alone, rather than have its corruption blessed by a new digest.
+    Unverified(fsck::Problem),
+
+    /// The new name is already taken, by the given file.
+    Conflict(PathBuf),
+
+    /// Could not be renamed.
+    Unrenamed(String),
+}
+
+impl Problem {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Unverified(problem) => problem.name(),
+            Self::Conflict(_) => "conflict",
+            Self::Unrenamed(_) => "unrenamed",
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    /// Old and new paths of the files renamed (or, in a dry run, of those
+    /// which would be).
+    pub renamed: Vec<(PathBuf, PathBuf)>,
+
+    /// Already named by the new hash.
+    pub current: usize,
+
+    pub problems: Vec<(PathBuf, Problem)>,
+}
+
+/// Rename the files under `dst_root` to carry digests of the given hash,
+/// instead of the ones they were organized with. Files are renamed in
+/// place, so semantic subdirectories are kept as they are. Each file is
+/// checked against its old digest, in the same pass as the new one is
+/// computed, and left alone if it does not match. Renames are recorded in
+/// the manifest, so that undo keeps working on the new names.
+#[tracing::instrument(level = "error", skip_all)]
+pub fn rehash<H: Hasher>(
+    dst_root: &Path,
+    hash: &H,
+    reading: Reading,
+    dry_run: bool,
+    show_progress: bool,
+) -> anyhow::Result<Report> {
+    tracing::info!(?dst_root, hash = hash.name(), dry_run, "Starting");
+    let dst_root = files::canonicalize(dst_root, "dst")?;
+    let progress_bar = files::progress_bar(show_progress)?;
+    let mut plan: Vec<(PathBuf, Result<Option<Rename>, Problem>)> =
+        FilePaths::find(&dst_root)
+            .filter(|path| !manifest::is_internal(&dst_root, path))
+            .par_bridge()
+            .map(|path| {
+                progress_bar.inc_length(1);
+                let planned = plan_file(&path, hash, reading);
+                progress_bar.inc(1);
+                (path, planned)
+            })
+            .collect();
+    progress_bar.finish();
+    // Renamed sequentially, since distinct files could be headed for the
+    // same new name.
+    plan.sort_by(|(a, _), (b, _)| a.cmp(b));
+    let mut report = Report::default();
+    let mut taken: HashSet<PathBuf> = HashSet::new();
+    let mut renames: Vec<(PathBuf, Rename)> = Vec::new();
+    for (path, planned) in plan {
+        let rename = match planned {
+            Ok(None) => {
+                report.current += 1;
+                continue;
+            }
+            Ok(Some(rename)) => rename,
+            Err(problem) => {
+                report.problems.push((path, problem));
+                continue;
+            }
+        };
+        let problem = match rename.dst.try_exists() {
+            Ok(true) => Some(Problem::Conflict(rename.dst.clone())),
+            Ok(false) if taken.contains(&rename.dst) => {
+                Some(Problem::Conflict(rename.dst.clone()))
+            }
+            Ok(false) if dry_run => None,
+            Ok(false) => fs::rename(&path, &rename.dst)
+                .err()
+                .map(|error| Problem::Unrenamed(error.to_string())),
+            Err(error) => Some(Problem::Unrenamed(error.to_string())),
+        };
+        match problem {
+            Some(problem) => {
+                tracing::warn!(?path, ?problem, "Not renaming");
+                report.problems.push((path, problem));
+            }
+            None => {
+                tracing::info!(src = ?path, dst = ?rename.dst, "Renamed");
+                taken.insert(rename.dst.clone());
+                report.renamed.push((path.clone(), rename.dst.clone()));
+                renames.push((path, rename));
+            }
+        }
+    }
+    if !dry_run {
+        record(&dst_root, &renames, hash)?;
+    }
+    tracing::info!(
+        renamed = report.renamed.len(),
+        current = report.current,
+        problems = report.problems.len(),
+        "Finished"
+    );
+    Ok(report)
+}
+
+#[derive(Debug)]
+struct Rename {
+    dst: PathBuf,
+    digest: String,
+}
+
+/// New path of the file, if it is not already named by the hash.
+#[tracing::instrument(level = "error", skip(hash, reading))]
+fn plan_file<H: Hasher>(
+    path: &Path,
+    hash: &H,
+    reading: Reading,
+) -> Result<Option<Rename>, Problem> {
+    let unverified = |problem| Err(Problem::Unverified(problem));
+    let Some(name) = path.file_name().map(|name| name.to_string_lossy())
+    else {
+        return unverified(fsck::Problem::Unrecognized);
+    };
+    if name.starts_with(files::TMP_FILE_PREFIX) {
+        return unverified(fsck::Problem::Incomplete);
+    }
+    let Some((old_hash, old_tag)) = files::find_digest(&name) else {
+        return unverified(fsck::Problem::Unrecognized);
+    };
+    let expected = old_tag.digest;
+    if old_hash.name() == hash.name() {
+        return Ok(None);
+    }
+    let mut old_digester = old_hash.digester();
+    let mut new_digester = hash.digester();
+    let fed = fs::File::open(path).and_then(|mut file| {
+        reading.feed(&mut file, |data| {
+            old_digester.update(data);
+            new_digester.update(data);
+        })
+    });
+    if let Err(error) = fed {
+        return unverified(fsck::Problem::Unreadable(error.to_string()));
+    }
+    let actual = old_digester.finalize();
+    if actual != expected {
+        return unverified(fsck::Problem::Corrupted {
+            expected: expected.to_string(),
+            actual,
+        });
+    }
+    let digest = new_digester.finalize();
+    let mut new_name = name.to_string();
+    new_name.replace_range(
+        old_tag.range,
+        &template::hash_tag(hash.name(), &digest, old_tag.portable),
+    );
+    Ok(Some(Rename {
+        dst: path.with_file_name(new_name),
+        digest,
+    }))
+}
+
+/// Supersede the manifest entries of the renamed files with ones under
+/// their new names, in the same runs, so that they can still be undone.
+fn record<H: Hasher>(
+    dst_root: &Path,
+    renames: &[(PathBuf, Rename)],
+    hash: &H,
+) -> anyhow::Result<()> {
+    let renames: HashMap<&Path, &Rename> = renames
+        .iter()
+        .filter_map(|(old, rename)| {
+            Some((old.strip_prefix(dst_root).ok()?, rename))
+        })
+        .collect();
+    let entries = manifest::read(dst_root)?;
+    // Already undone, or renamed by an earlier rehash.
+    let superseded: HashSet<(String, PathBuf)> = entries
+        .iter()
+        .filter(|entry| {
+            matches!(entry.outcome, Outcome::Undone | Outcome::Rehashed)
+        })
+        .map(|entry| (entry.run_id.clone(), entry.dst.clone()))
+        .collect();
+    let entries: Vec<manifest::Entry> = entries
+        .into_iter()
+        .filter(|entry| {
+            entry.outcome == Outcome::Organized
+                && renames.contains_key(entry.dst.as_path())
+                && !superseded
+                    .contains(&(entry.run_id.clone(), entry.dst.clone()))
+        })
+        .collect();
+    if entries.is_empty() {
+        return Ok(());
+    }
+    let manifest = manifest::Writer::open(dst_root)?;
+    let time = chrono::Local::now().to_rfc3339();
+    for entry in entries {
+        let rename = renames[entry.dst.as_path()];
+        let Ok(dst) = rename.dst.strip_prefix(dst_root) else {
+            continue;
+        };
+        let new = manifest::Entry {
+            time: time.clone(),
+            dst: dst.to_path_buf(),
+            hash: hash.name().to_string(),
+            digest: rename.digest.clone(),
+            run_id: entry.run_id.clone(),
+            src: entry.src.clone(),
+            error: None,
+            ..entry
+        };
+        let old = manifest::Entry {
+            time: time.clone(),
+            outcome: Outcome::Rehashed,
+            ..entry
+        };
+        manifest.append(&old)?;
+        manifest.append(&new)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,219 @@
+use std::{
+    fs,
+    io::{self, Cursor, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use crate::{
+    files::Typ,
+    hash::{Digester, Hasher, Reading},
+};
+
+/// Files up to this size are read into memory whole, in a single read.
+const BUFFERED_MAX: u64 = 32 * 1024 * 1024;
+
+/// Enough for infer to recognize any of the types it knows.
+const HEADER_LEN: usize = 8192;
+
+/// A src file, opened once, from which its type, metadata and digest are
+/// all read. Only the header is read upfront, for the type. Past that, small
+/// files are read whole into memory, while large ones are streamed and
+/// hashed as they are read by the metadata parsers, so that only the
+/// remainder needs to be read when computing the digest.
+pub struct Source<'a, H: Hasher> {
+    header: Vec<u8>,
+    reader: Reader<H::Digester>,
+    metadata: fs::Metadata,
+    hash: &'a H,
+    reading: Reading,
+}
+
+enum Reader<D> {
+    /// Small file, of which only the header was read so far.
+    Pending(fs::File),
+    Buffered(Cursor<Vec<u8>>),
+    Streamed(Hashing<fs::File, D>),
+}
+
+impl<'a, H: Hasher> Source<'a, H> {
+    pub fn open(
+        path: &Path,
+        hash: &'a H,
+        reading: Reading,
+    ) -> io::Result<Self> {
+        let file = fs::File::open(path)?;
+        let metadata = file.metadata()?;
+        let mut header = Vec::with_capacity(HEADER_LEN);
+        let reader = if metadata.len() <= BUFFERED_MAX {
+            let mut file = file;
+            file.by_ref()
+                .take(HEADER_LEN as u64)
+                .read_to_end(&mut header)?;
+            Reader::Pending(file)
+        } else {
+            let mut hashing = Hashing::new(file, hash.digester());
+            hashing
+                .by_ref()
+                .take(HEADER_LEN as u64)
+                .read_to_end(&mut header)?;
+            hashing.rewind()?;
+            Reader::Streamed(hashing)
+        };
+        Ok(Self {
+            header,
+            reader,
+            metadata,
+            hash,
+            reading,
+        })
+    }
+
+    pub fn metadata(&self) -> &fs::Metadata {
+        &self.metadata
+    }
+
+    pub fn typ(&self) -> Option<Typ> {
+        let matcher_type_opt = infer::get(&self.header);
+        tracing::debug!(?matcher_type_opt, "Read");
+        matcher_type_opt.map(|typ| typ.matcher_type()).and_then(
+            |matcher_type| match matcher_type {
+                infer::MatcherType::Image => Some(Typ::Img),
+                infer::MatcherType::Video => Some(Typ::Vid),
+                _ => None,
+            },
+        )
+    }
+
+    /// Hex-encoded digest of the whole file.
+    pub fn digest(mut self) -> io::Result<String> {
+        self.loaded()?;
+        match self.reader {
+            Reader::Pending(_) => unreachable!("Loaded source is pending."),
+            Reader::Buffered(data) => {
+                let mut digester = self.hash.digester();
+                digester.update(data.get_ref());
+                Ok(digester.finalize())
+            }
+            Reader::Streamed(hashing) => {
+                let Hashing {
+                    mut inner,
+                    mut digester,
+                    hashed,
+                    ..
+                } = hashing;
+                inner.seek(SeekFrom::Start(hashed))?;
+                self.reading
+                    .feed(&mut inner, |data| digester.update(data))?;
+                Ok(digester.finalize())
+            }
+        }
+    }
+
+    /// Read the rest of a small file, if not yet read.
+    fn loaded(&mut self) -> io::Result<&mut Reader<H::Digester>> {
+        if let Reader::Pending(file) = &mut self.reader {
+            let mut data = std::mem::take(&mut self.header);
+            file.read_to_end(&mut data)?;
+            self.header = data[..data.len().min(HEADER_LEN)].to_vec();
+            self.reader = Reader::Buffered(Cursor::new(data));
+        }
+        Ok(&mut self.reader)
+    }
+}
+
+impl<H: Hasher> Read for Source<'_, H> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.loaded()? {
+            Reader::Pending(_) => unreachable!("Loaded source is pending."),
+            Reader::Buffered(data) => data.read(buf),
+            Reader::Streamed(hashing) => hashing.read(buf),
+        }
+    }
+}
+
+impl<H: Hasher> Seek for Source<'_, H> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self.loaded()? {
+            Reader::Pending(_) => unreachable!("Loaded source is pending."),
+            Reader::Buffered(data) => data.seek(pos),
+            Reader::Streamed(hashing) => hashing.seek(pos),
+        }
+    }
+}
+
+/// Reader which feeds the digester the data read, as long as it is read in
+/// order. Data read out of order, after a seek, is not hashed, but neither
+/// does it prevent the hashing from picking-up where it left off, once
+/// reads get there again.
+struct Hashing<R, D> {
+    inner: R,
+    digester: D,
+
+    /// Current position.
+    pos: u64,
+
+    /// Length of the prefix which was fed to the digester.
+    hashed: u64,
+}
+
+impl<R, D> Hashing<R, D> {
+    fn new(inner: R, digester: D) -> Self {
+        Self {
+            inner,
+            digester,
+            pos: 0,
+            hashed: 0,
+        }
+    }
+}
+
+impl<R: Read, D: Digester> Read for Hashing<R, D> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        let end = self.pos + n as u64;
+        if self.pos <= self.hashed && self.hashed < end {
+            let unhashed = (self.hashed - self.pos) as usize;
+            self.digester.update(&buf[unhashed..n]);
+            self.hashed = end;
+        }
+        self.pos = end;
+        Ok(n)
+    }
+}
+
+impl<R: Seek, D> Seek for Hashing<R, D> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.pos = self.inner.seek(pos)?;
+        Ok(self.pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::Hash;
+
+    #[test]
+    fn t_hashing() {
+        let data: Vec<u8> =
+            (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        let expected = {
+            let mut digester = Hash::Sha256.digester();
+            digester.update(&data);
+            digester.finalize()
+        };
+        let mut hashing =
+            Hashing::new(Cursor::new(&data[..]), Hash::Sha256.digester());
+        let mut buf = vec![0; 1000];
+        // Header, then metadata out of order, then back to the start.
+        hashing.read_exact(&mut buf[..100]).unwrap();
+        hashing.seek(SeekFrom::Start(50_000)).unwrap();
+        hashing.read_exact(&mut buf).unwrap();
+        hashing.rewind().unwrap();
+        hashing.read_exact(&mut buf).unwrap();
+        assert_eq!(1000, hashing.hashed);
+        hashing.seek(SeekFrom::Start(hashing.hashed)).unwrap();
+        io::copy(&mut hashing, &mut io::sink()).unwrap();
+        assert_eq!(expected, hashing.digester.finalize());
+    }
+}
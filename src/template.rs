@@ -0,0 +1,410 @@
+use std::{path::PathBuf, str::FromStr};
+
+/// The layout used before templates became configurable.
+pub const DEFAULT: &str = "{year}/{month}/{day}/{date}--{time}--{hash}.{ext}";
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Unknown placeholder: {{{0}}}")]
+    UnknownVar(String),
+
+    #[error("Unclosed placeholder in: {0:?}")]
+    Unclosed(String),
+
+    #[error("Invalid path component: {0:?}")]
+    InvalidComponent(String),
+
+    #[error("File name component must contain {{hash}}")]
+    MissingHash,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Var {
+    Year,
+    Month,
+    Day,
+    Hour,
+    Minute,
+    Second,
+    Subsec,
+    Date,
+    Time,
+    Hash,
+    Model,
+    Ext,
+}
+
+impl FromStr for Var {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "year" => Ok(Self::Year),
+            "month" => Ok(Self::Month),
+            "day" => Ok(Self::Day),
+            "hour" => Ok(Self::Hour),
+            "minute" => Ok(Self::Minute),
+            "second" => Ok(Self::Second),
+            "subsec" => Ok(Self::Subsec),
+            "date" => Ok(Self::Date),
+            "time" => Ok(Self::Time),
+            "hash" => Ok(Self::Hash),
+            "model" => Ok(Self::Model),
+            "ext" => Ok(Self::Ext),
+            _ => Err(Error::UnknownVar(s.to_string())),
+        }
+    }
+}
+
+impl Var {
+    fn render(self, vars: &Vars) -> String {
+        use chrono::{Datelike, Timelike}; // Access timestamp fields.
+
+        let ts = vars.timestamp;
+        match self {
+            Self::Year => format!("{:02}", ts.year()),
+            Self::Month => format!("{:02}", ts.month()),
+            Self::Day => format!("{:02}", ts.day()),
+            Self::Hour => format!("{:02}", ts.hour()),
+            Self::Minute => format!("{:02}", ts.minute()),
+            Self::Second => format!("{:02}", ts.second()),
+            Self::Subsec => ts.format("%3f").to_string(),
+            Self::Date => ts.format("%Y-%m-%d").to_string(),
+            Self::Time if vars.portable => ts.format("%H-%M-%S").to_string(),
+            Self::Time => ts.format("%H:%M:%S").to_string(),
+            Self::Hash => {
+                hash_tag(vars.hash_name, vars.digest, vars.portable)
+            }
+            Self::Model => {
+                sanitize(vars.model.unwrap_or_default(), vars.portable)
+            }
+            Self::Ext => vars.ext.to_string(),
+        }
+    }
+
+    /// Does the given string look like something this var renders into?
+    fn accepts(self, s: &str) -> bool {
+        fn is_num(s: &str, max_len: usize) -> bool {
+            !s.is_empty()
+                && s.len() <= max_len
+                && s.chars().all(|c| c.is_ascii_digit())
+        }
+
+        fn is_nums(s: &str, sep: char, max_lens: &[usize]) -> bool {
+            let parts: Vec<&str> = s.split(sep).collect();
+            parts.len() == max_lens.len()
+                && parts
+                    .iter()
+                    .zip(max_lens)
+                    .all(|(part, max_len)| is_num(part, *max_len))
+        }
+
+        match self {
+            Self::Year => is_num(s, usize::MAX),
+            Self::Month
+            | Self::Day
+            | Self::Hour
+            | Self::Minute
+            | Self::Second => is_num(s, 2),
+            Self::Subsec => s.len() == 3 && is_num(s, 3),
+            Self::Date => is_nums(s, '-', &[usize::MAX, 2, 2]),
+            Self::Time => {
+                is_nums(s, ':', &[2, 2, 2]) || is_nums(s, '-', &[2, 2, 2])
+            }
+            Self::Hash => crate::files::find_digest(s)
+                .is_some_and(|(_, tag)| tag.range == (0..s.len())),
+            Self::Model | Self::Ext => !s.is_empty(),
+        }
+    }
+}
+
+/// The `<hash name>:<digest>` part of a file name, in which `:` is replaced
+/// by `-` when portable.
+pub fn hash_tag(hash_name: &str, digest: &str, portable: bool) -> String {
+    let sep = if portable { "-" } else { ":" };
+    [hash_name, digest].join(sep)
+}
+
+/// Replace characters which cannot be in a path component. When portable,
+/// also the ones reserved on FAT/exFAT and SMB. Values which would not name
+/// a component of their own, such as `..`, become "unknown".
+pub fn sanitize(s: &str, portable: bool) -> String {
+    let s: String = s
+        .trim()
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' => '_',
+            ':' | '*' | '?' | '"' | '<' | '>' | '|' if portable => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    match s.as_str() {
+        // Not names, but references to this or the parent directory.
+        "" | "." | ".." => "unknown".to_string(),
+        _ => s,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Piece {
+    Lit(String),
+    Var(Var),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Component(Vec<Piece>);
+
+impl FromStr for Component {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() || s == "." || s == ".." {
+            return Err(Error::InvalidComponent(s.to_string()));
+        }
+        let mut pieces = Vec::new();
+        let mut rest = s;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                pieces.push(Piece::Lit(rest[..start].to_string()));
+            }
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| Error::Unclosed(s.to_string()))?;
+            let var = rest[start + 1..start + end].parse()?;
+            pieces.push(Piece::Var(var));
+            rest = &rest[start + end + 1..];
+        }
+        if !rest.is_empty() {
+            pieces.push(Piece::Lit(rest.to_string()));
+        }
+        Ok(Self(pieces))
+    }
+}
+
+impl Component {
+    fn render(&self, vars: &Vars) -> String {
+        let rendered: String = self
+            .0
+            .iter()
+            .map(|piece| match piece {
+                Piece::Lit(lit) => lit.clone(),
+                Piece::Var(var) => var.render(vars),
+            })
+            .collect();
+        // An empty {ext} should not leave a dangling dot behind.
+        match rendered.strip_suffix('.') {
+            Some(stripped) if vars.ext.is_empty() => stripped.to_string(),
+            _ => rendered,
+        }
+    }
+
+    fn has(&self, var: Var) -> bool {
+        self.0.contains(&Piece::Var(var))
+    }
+
+    fn matches(&self, s: &str) -> bool {
+        fn go(pieces: &[Piece], s: &str) -> bool {
+            match pieces.split_first() {
+                None => s.is_empty(),
+                Some((Piece::Lit(lit), rest)) => {
+                    s.strip_prefix(lit.as_str()).is_some_and(|s| go(rest, s))
+                }
+                Some((Piece::Var(var), rest)) => (1..=s.len())
+                    .filter(|n| s.is_char_boundary(*n))
+                    .any(|n| var.accepts(&s[..n]) && go(rest, &s[n..])),
+            }
+        }
+        go(&self.0, s)
+    }
+}
+
+/// Values to fill-in a template with.
+#[derive(Debug)]
+pub struct Vars<'a> {
+    /// Wall-clock time, in the zone chosen for paths.
+    pub timestamp: chrono::NaiveDateTime,
+    pub hash_name: &'a str,
+    pub digest: &'a str,
+    pub model: Option<&'a str>,
+    pub ext: &'a str,
+
+    /// Avoid characters which are illegal on FAT/exFAT and SMB, i.e.
+    /// separate time and hash parts with `-` instead of `:`.
+    pub portable: bool,
+}
+
+/// Layout of the paths under the type directory, parsed from a string of
+/// `/`-separated components, in which `{<var>}` placeholders are replaced
+/// by file attributes. Available vars: year, month, day, hour, minute,
+/// second, subsec (milliseconds), date, time, hash, model, ext. The last
+/// component is the file name and it must contain `{hash}`.
+#[derive(Debug, Clone)]
+pub struct Template {
+    dirs: Vec<Component>,
+    name: Component,
+}
+
+impl Default for Template {
+    fn default() -> Self {
+        DEFAULT.parse().unwrap_or_else(|error| {
+            unreachable!("Invalid default template: {:?}", error)
+        })
+    }
+}
+
+impl FromStr for Template {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut dirs = s
+            .split('/')
+            .map(Component::from_str)
+            .collect::<Result<Vec<_>, _>>()?;
+        let name = dirs
+            .pop()
+            .ok_or_else(|| Error::InvalidComponent(s.to_string()))?;
+        if !name.has(Var::Hash) {
+            return Err(Error::MissingHash);
+        }
+        Ok(Self { dirs, name })
+    }
+}
+
+impl Template {
+    pub fn render_dir(&self, vars: &Vars) -> PathBuf {
+        self.dirs.iter().map(|dir| dir.render(vars)).collect()
+    }
+
+    pub fn render_name(&self, vars: &Vars) -> String {
+        self.name.render(vars)
+    }
+
+    /// Number of directory components preceding the file name.
+    pub fn depth(&self) -> usize {
+        self.dirs.len()
+    }
+
+    /// Do the given directory components look like they were rendered from
+    /// this template?
+    pub fn matches_dirs(&self, dirs: &[&str]) -> bool {
+        dirs.len() == self.dirs.len()
+            && self.dirs.iter().zip(dirs).all(|(c, s)| c.matches(s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_render() {
+        let vars = Vars {
+            timestamp: chrono::NaiveDateTime::new(
+                chrono::NaiveDate::from_ymd_opt(2022, 10, 3).unwrap(),
+                chrono::NaiveTime::from_hms_milli_opt(17, 52, 16, 59)
+                    .unwrap(),
+            ),
+            hash_name: "crc32",
+            digest: "75daa68d",
+            model: Some("Canon EOS 5D/II"),
+            ext: "jpg",
+            portable: false,
+        };
+
+        let template = Template::default();
+        assert_eq!(PathBuf::from("2022/10/03"), template.render_dir(&vars));
+        assert_eq!(
+            "2022-10-03--17:52:16--crc32:75daa68d.jpg",
+            template.render_name(&vars)
+        );
+
+        let template: Template =
+            "{year}/{year}-{month}/{date}--{time}--{model}--{hash}.{ext}"
+                .parse()
+                .unwrap();
+        assert_eq!(PathBuf::from("2022/2022-10"), template.render_dir(&vars));
+        assert_eq!(
+            "2022-10-03--17:52:16--Canon EOS 5D_II--crc32:75daa68d.jpg",
+            template.render_name(&vars)
+        );
+
+        let bursts: Template =
+            "{date}/{time}.{subsec}--{hash}.{ext}".parse().unwrap();
+        assert_eq!(
+            "17:52:16.059--crc32:75daa68d.jpg",
+            bursts.render_name(&vars)
+        );
+
+        let vars = Vars { ext: "", ..vars };
+        assert_eq!(
+            "2022-10-03--17:52:16--Canon EOS 5D_II--crc32:75daa68d",
+            template.render_name(&vars)
+        );
+
+        let vars = Vars {
+            portable: true,
+            model: Some("Foo: Bar?"),
+            ..vars
+        };
+        assert_eq!(
+            "2022-10-03--17-52-16--Foo_ Bar_--crc32-75daa68d",
+            template.render_name(&vars)
+        );
+    }
+
+    #[test]
+    fn t_sanitize() {
+        assert_eq!("Canon EOS 5D", sanitize(" Canon EOS 5D ", false));
+        assert_eq!("a_b_c", sanitize("a/b\\c", false));
+        assert_eq!("a:b", sanitize("a:b", false));
+        assert_eq!("a_b", sanitize("a:b", true));
+        for s in ["", "  ", ".", "..", " .. "] {
+            assert_eq!("unknown", sanitize(s, false), "{:?}", s);
+        }
+        assert_eq!("...", sanitize("...", false));
+    }
+
+    #[test]
+    fn t_parse() {
+        assert!("{date}--{hash}.{ext}".parse::<Template>().is_ok());
+        assert!(matches!(
+            "{year}/{date}.{ext}".parse::<Template>(),
+            Err(Error::MissingHash)
+        ));
+        assert!(matches!(
+            "{year}/{foo}/{hash}".parse::<Template>(),
+            Err(Error::UnknownVar(_))
+        ));
+        assert!(matches!(
+            "{year/{hash}".parse::<Template>(),
+            Err(Error::Unclosed(_))
+        ));
+        assert!(matches!(
+            "{year}//{hash}".parse::<Template>(),
+            Err(Error::InvalidComponent(_))
+        ));
+        assert!(matches!(
+            "{year}/../{hash}".parse::<Template>(),
+            Err(Error::InvalidComponent(_))
+        ));
+    }
+
+    #[test]
+    fn t_matches_dirs() {
+        let template = Template::default();
+        assert!(template.matches_dirs(&["2009", "01", "07"]));
+        assert!(!template.matches_dirs(&["2009", "01"]));
+        assert!(!template.matches_dirs(&["2009", "01", "foo"]));
+
+        let template: Template =
+            "{year}/{year}-{month}/{hash}.{ext}".parse().unwrap();
+        assert!(template.matches_dirs(&["2009", "2009-01"]));
+        assert!(!template.matches_dirs(&["2009", "200901"]));
+
+        let template: Template =
+            "{year}/{date}--{time}/{hash}.{ext}".parse().unwrap();
+        assert!(template.matches_dirs(&["2009", "2009-01-07--17:52:16"]));
+        assert!(template.matches_dirs(&["2009", "2009-01-07--17-52-16"]));
+    }
+}
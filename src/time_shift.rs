@@ -0,0 +1,191 @@
+use std::{collections::HashMap, str::FromStr};
+
+use crate::timestamp::Timestamp;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Empty time shift")]
+    Empty,
+
+    #[error("Missing number before unit in: {0:?}")]
+    MissingNumber(String),
+
+    #[error("Missing unit after number in: {0:?}")]
+    MissingUnit(String),
+
+    #[error("Unknown unit {0:?}, expected one of: d, h, m, s")]
+    UnknownUnit(char),
+
+    #[error("Time shift out of range: {0:?}")]
+    OutOfRange(String),
+
+    #[error("Expected <MODEL>=<SHIFT>, got: {0:?}")]
+    MissingModel(String),
+}
+
+/// Correction for a wrong camera clock, parsed from an optionally-signed
+/// sequence of `<number><unit>` parts, where unit is one of `d`, `h`, `m`
+/// or `s`, e.g. "+9h", "-1d 3m" (i.e. minus 1 day and 3 minutes) or
+/// "1h30m".
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TimeShift(chrono::TimeDelta);
+
+impl FromStr for TimeShift {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let out_of_range = || Error::OutOfRange(s.to_string());
+        let trimmed = s.trim();
+        let (sign, rest) = match trimmed.split_at_checked(1) {
+            Some(("-", rest)) => (-1, rest),
+            Some(("+", rest)) => (1, rest),
+            _ => (1, trimmed),
+        };
+        let mut total = chrono::TimeDelta::zero();
+        let mut num = String::new();
+        let mut parts = 0;
+        for c in rest.chars() {
+            match c {
+                c if c.is_ascii_digit() => num.push(c),
+                c if c.is_whitespace() && num.is_empty() => {}
+                c if c.is_whitespace() => {
+                    return Err(Error::MissingUnit(s.to_string()));
+                }
+                unit => {
+                    if num.is_empty() {
+                        return Err(Error::MissingNumber(s.to_string()));
+                    }
+                    let n: i64 = num.parse().map_err(|_| out_of_range())?;
+                    let part = match unit {
+                        'd' => chrono::TimeDelta::try_days(n),
+                        'h' => chrono::TimeDelta::try_hours(n),
+                        'm' => chrono::TimeDelta::try_minutes(n),
+                        's' => chrono::TimeDelta::try_seconds(n),
+                        _ => return Err(Error::UnknownUnit(unit)),
+                    };
+                    total = part
+                        .and_then(|part| total.checked_add(&part))
+                        .ok_or_else(out_of_range)?;
+                    num.clear();
+                    parts += 1;
+                }
+            }
+        }
+        if !num.is_empty() {
+            return Err(Error::MissingUnit(s.to_string()));
+        }
+        if parts == 0 {
+            return Err(Error::Empty);
+        }
+        Ok(Self(total * sign))
+    }
+}
+
+impl TimeShift {
+    /// Shifted timestamp, or the original, if shifting would overflow.
+    /// The offset is kept, since it is the clock that was wrong, not the
+    /// zone.
+    pub fn apply(&self, timestamp: Timestamp) -> Timestamp {
+        match timestamp.local.checked_add_signed(self.0) {
+            Some(local) => Timestamp { local, ..timestamp },
+            None => {
+                tracing::warn!(?timestamp, shift = ?self, "Out of range");
+                timestamp
+            }
+        }
+    }
+}
+
+/// A camera model and its clock correction, parsed from `<MODEL>=<SHIFT>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModelTimeShift {
+    pub model: String,
+    pub shift: TimeShift,
+}
+
+impl FromStr for ModelTimeShift {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (model, shift) = s
+            .rsplit_once('=')
+            .filter(|(model, _)| !model.trim().is_empty())
+            .ok_or_else(|| Error::MissingModel(s.to_string()))?;
+        Ok(Self {
+            model: model.trim().to_string(),
+            shift: shift.parse()?,
+        })
+    }
+}
+
+/// Clock corrections to apply to timestamps: the one for the camera model,
+/// if any, otherwise the default.
+#[derive(Debug, Default, Clone)]
+pub struct TimeShifts {
+    pub default: TimeShift,
+    pub by_model: HashMap<String, TimeShift>,
+}
+
+impl TimeShifts {
+    pub fn apply(
+        &self,
+        timestamp: Timestamp,
+        model: Option<&str>,
+    ) -> Timestamp {
+        model
+            .and_then(|model| self.by_model.get(model.trim()))
+            .unwrap_or(&self.default)
+            .apply(timestamp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn t_parse() {
+        let shift = |s: &str| s.parse::<TimeShift>().map(|shift| shift.0);
+        assert_eq!(chrono::TimeDelta::hours(9), shift("+9h").unwrap());
+        assert_eq!(chrono::TimeDelta::hours(9), shift("9h").unwrap());
+        assert_eq!(
+            -(chrono::TimeDelta::days(1) + chrono::TimeDelta::minutes(3)),
+            shift("-1d 3m").unwrap()
+        );
+        assert_eq!(
+            chrono::TimeDelta::minutes(90) + chrono::TimeDelta::seconds(5),
+            shift("1h30m5s").unwrap()
+        );
+        assert!(matches!(shift(""), Err(Error::Empty)));
+        assert!(matches!(shift("-"), Err(Error::Empty)));
+        assert!(matches!(shift("9"), Err(Error::MissingUnit(_))));
+        assert!(matches!(shift("9 h"), Err(Error::MissingUnit(_))));
+        assert!(matches!(shift("h"), Err(Error::MissingNumber(_))));
+        assert!(matches!(shift("9y"), Err(Error::UnknownUnit('y'))));
+    }
+
+    #[test]
+    fn t_apply() {
+        let ts = |s: &str| {
+            Timestamp::from(
+                chrono::NaiveDateTime::parse_from_str(s, "%F %T").unwrap(),
+            )
+        };
+        let shifts = TimeShifts {
+            default: "+9h".parse().unwrap(),
+            by_model: ["Canon EOS 5D=-1d 3m"
+                .parse::<ModelTimeShift>()
+                .unwrap()]
+            .into_iter()
+            .map(|ModelTimeShift { model, shift }| (model, shift))
+            .collect(),
+        };
+        let t = ts("2022-10-03 17:52:16");
+        assert_eq!(ts("2022-10-04 02:52:16"), shifts.apply(t, None));
+        assert_eq!(ts("2022-10-04 02:52:16"), shifts.apply(t, Some("Foo")));
+        assert_eq!(
+            ts("2022-10-02 17:49:16"),
+            shifts.apply(t, Some("Canon EOS 5D"))
+        );
+    }
+}
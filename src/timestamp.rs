@@ -0,0 +1,287 @@
+use std::{fmt, str::FromStr};
+
+use chrono::{FixedOffset, NaiveDateTime, Offset};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Invalid UTC offset: {0:?}, expected ±HH:MM")]
+    InvalidOffset(String),
+
+    #[error("Invalid timestamp: {0:?}")]
+    InvalidTimestamp(String),
+}
+
+/// Wall-clock date and time at which a photo/video was taken, along with
+/// the offset from UTC of the place where it was taken, when known.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timestamp {
+    pub local: NaiveDateTime,
+    pub offset: Option<FixedOffset>,
+
+    /// Recorded in UTC only, e.g. QuickTime's mvhd time, so the offset is
+    /// UTC's, rather than that of the place where it was taken.
+    pub utc_only: bool,
+}
+
+impl Timestamp {
+    pub fn new(local: NaiveDateTime, offset: Option<FixedOffset>) -> Self {
+        Self {
+            local,
+            offset,
+            utc_only: false,
+        }
+    }
+
+    /// Wall-clock time of the given instant in its own zone.
+    pub fn from_date_time<Tz: chrono::TimeZone>(
+        t: &chrono::DateTime<Tz>,
+    ) -> Self {
+        Self::new(t.naive_local(), Some(t.offset().fix()))
+    }
+
+    /// Instant recorded in UTC only, without the capture zone.
+    pub fn from_utc(utc: NaiveDateTime) -> Self {
+        Self {
+            utc_only: true,
+            ..Self::new(utc, Some(chrono::Utc.fix()))
+        }
+    }
+
+    /// Wall-clock time in the given zone. Timestamps with an unknown offset
+    /// are assumed to already be in it. For those recorded in UTC only, the
+    /// capture zone is assumed to be the given one.
+    pub fn in_zone(&self, zone: Zone, assumed: AssumedZone) -> NaiveDateTime {
+        let utc = |offset: FixedOffset| (self.local - offset).and_utc();
+        match (zone, self.offset) {
+            (Zone::Capture, Some(offset)) if self.utc_only => match assumed {
+                AssumedZone::Local => {
+                    utc(offset).with_timezone(&chrono::Local).naive_local()
+                }
+                AssumedZone::Fixed(zone) => {
+                    utc(offset).with_timezone(&zone).naive_local()
+                }
+            },
+            (Zone::Capture, _) | (Zone::Fixed(_), None) => self.local,
+            (Zone::Fixed(zone), Some(offset)) => {
+                utc(offset).with_timezone(&zone).naive_local()
+            }
+        }
+    }
+}
+
+impl From<NaiveDateTime> for Timestamp {
+    fn from(local: NaiveDateTime) -> Self {
+        Self::new(local, None)
+    }
+}
+
+/// RFC 3339 when the offset is known, otherwise the same without the
+/// offset. Times recorded in UTC only end in `Z`, rather than `+00:00`.
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.local.format("%Y-%m-%dT%H:%M:%S%.f"))?;
+        match self.offset {
+            None => Ok(()),
+            Some(_) if self.utc_only => write!(f, "Z"),
+            Some(offset) => write!(f, "{}", offset),
+        }
+    }
+}
+
+impl FromStr for Timestamp {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        chrono::DateTime::parse_from_rfc3339(s)
+            .map(|t| match s.ends_with(['Z', 'z']) {
+                true => Self::from_utc(t.naive_utc()),
+                false => Self::from_date_time(&t),
+            })
+            .or_else(|_| NaiveDateTime::from_str(s).map(Self::from))
+            .map_err(|_| Error::InvalidTimestamp(s.to_string()))
+    }
+}
+
+impl serde::Serialize for Timestamp {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Timestamp {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        let s = <std::borrow::Cow<str>>::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Zone in which to express timestamps in destination paths.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Zone {
+    /// Wall-clock time where the photo/video was taken.
+    #[default]
+    Capture,
+
+    /// Fixed offset from UTC.
+    Fixed(FixedOffset),
+}
+
+impl FromStr for Zone {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "capture" => Ok(Self::Capture),
+            _ => parse_offset(s).map(Self::Fixed),
+        }
+    }
+}
+
+/// Zone assumed to be the capture zone of the timestamps recorded in UTC
+/// only, when expressing them in the capture zone.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum AssumedZone {
+    /// This machine's zone.
+    #[default]
+    Local,
+
+    /// Fixed offset from UTC.
+    Fixed(FixedOffset),
+}
+
+impl FromStr for AssumedZone {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "local" => Ok(Self::Local),
+            _ => parse_offset(s).map(Self::Fixed),
+        }
+    }
+}
+
+/// Parse the digits of a fraction of a second, such as in Exif's
+/// SubSecTimeOriginal, into nanoseconds.
+pub fn parse_subsec(s: &str) -> Option<u32> {
+    let digits = s.trim();
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let digits = &digits[..digits.len().min(9)];
+    let nanos: u32 = digits.parse().ok()?;
+    Some(nanos * 10u32.pow(9 - digits.len() as u32))
+}
+
+/// Parse a UTC offset such as "+09:00", "-0700", "+4:00" or "Z".
+pub fn parse_offset(s: &str) -> Result<FixedOffset, Error> {
+    let invalid = || Error::InvalidOffset(s.to_string());
+    if s == "Z" {
+        return Ok(chrono::Utc.fix());
+    }
+    let (sign, rest) = match s.split_at_checked(1).ok_or_else(invalid)? {
+        ("+", rest) => (1, rest),
+        ("-", rest) => (-1, rest),
+        _ => return Err(invalid()),
+    };
+    let (hours, minutes) = match rest.split_once(':') {
+        Some((hours, minutes)) => (hours, minutes),
+        None if rest.len() == 4 => rest.split_at(2),
+        None => (rest, "0"),
+    };
+    let num = |s: &str, max_len: usize| {
+        (!s.is_empty() && s.len() <= max_len)
+            .then(|| s.parse::<i32>().ok())
+            .flatten()
+            .ok_or_else(invalid)
+    };
+    let seconds = num(hours, 2)? * 3600 + num(minutes, 2)? * 60;
+    FixedOffset::east_opt(sign * seconds).ok_or_else(invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn local(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%F %T").unwrap()
+    }
+
+    #[test]
+    fn t_parse_offset() {
+        let east = |h: i32, m: i32| {
+            FixedOffset::east_opt(h * 3600 + m * 60).unwrap()
+        };
+        assert_eq!(east(9, 0), parse_offset("+09:00").unwrap());
+        assert_eq!(east(-7, 0), parse_offset("-0700").unwrap());
+        assert_eq!(east(4, 0), parse_offset("+4:00").unwrap());
+        assert_eq!(east(5, 30), parse_offset("+05:30").unwrap());
+        assert_eq!(east(0, 0), parse_offset("Z").unwrap());
+        assert!(parse_offset("").is_err());
+        assert!(parse_offset("09:00").is_err());
+        assert!(parse_offset("+99:00").is_err());
+        assert!(parse_offset("+09:xx").is_err());
+    }
+
+    #[test]
+    fn t_parse_subsec() {
+        assert_eq!(Some(590_000_000), parse_subsec("59"));
+        assert_eq!(Some(59_000_000), parse_subsec("059 "));
+        assert_eq!(Some(597_752_928), parse_subsec("597752928733826"));
+        assert_eq!(None, parse_subsec(""));
+        assert_eq!(None, parse_subsec("5x"));
+    }
+
+    #[test]
+    fn t_in_zone() {
+        let t = Timestamp::new(
+            local("2022-10-03 17:52:16"),
+            Some(parse_offset("+09:00").unwrap()),
+        );
+        let utc = Zone::Fixed(parse_offset("Z").unwrap());
+        let tokyo = AssumedZone::Fixed(parse_offset("+09:00").unwrap());
+        let assumed = AssumedZone::default();
+        assert_eq!(
+            local("2022-10-03 17:52:16"),
+            t.in_zone(Zone::Capture, assumed)
+        );
+        assert_eq!(local("2022-10-03 08:52:16"), t.in_zone(utc, assumed));
+        let t = Timestamp::from(local("2022-10-03 17:52:16"));
+        assert_eq!(local("2022-10-03 17:52:16"), t.in_zone(utc, assumed));
+
+        // Recorded in UTC only.
+        let t = Timestamp::from_utc(local("2022-10-03 08:52:16"));
+        assert_eq!(
+            local("2022-10-03 17:52:16"),
+            t.in_zone(Zone::Capture, tokyo)
+        );
+        assert_eq!(local("2022-10-03 08:52:16"), t.in_zone(utc, tokyo));
+        let instant = local("2022-10-03 08:52:16").and_utc();
+        assert_eq!(
+            instant.with_timezone(&chrono::Local).naive_local(),
+            t.in_zone(Zone::Capture, assumed)
+        );
+    }
+
+    #[test]
+    fn t_display_parse() {
+        for s in [
+            "2022-10-03T17:52:16+09:00",
+            "2022-10-03T17:52:16+00:00",
+            "2022-10-03T17:52:16Z",
+            "2022-10-03T17:52:16",
+        ] {
+            assert_eq!(s, s.parse::<Timestamp>().unwrap().to_string());
+        }
+        assert!(
+            "2022-10-03T17:52:16Z"
+                .parse::<Timestamp>()
+                .unwrap()
+                .utc_only
+        );
+    }
+}
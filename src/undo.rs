@@ -0,0 +1,143 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+
+use crate::{
+    files::{self, Op, Outcome},
+    hash::{Hash, Hasher, Reading},
+    manifest,
+};
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub undone: usize,
+
+    /// dst paths which were not touched and why.
+    pub refused: Vec<(PathBuf, String)>,
+}
+
+/// Reverse the organize run recorded in the manifest under the given ID:
+/// move moved files back to their src, delete copied/linked dsts. Files
+/// which have been modified since the run are left alone. They are checked
+/// by the given hash where it is the one recorded, e.g. a custom one, and
+/// otherwise by the built-in hash of the recorded name.
+#[tracing::instrument(level = "error", skip(dst_root, hash))]
+pub fn undo<H: Hasher>(
+    dst_root: &Path,
+    run_id: &str,
+    hash: &H,
+    reading: Reading,
+) -> anyhow::Result<Report> {
+    let dst_root = dst_root.canonicalize().context(format!(
+        "Failed to canonicalize dst path: {:?}",
+        dst_root
+    ))?;
+    let entries: Vec<manifest::Entry> = manifest::read(&dst_root)?
+        .into_iter()
+        .filter(|entry| entry.run_id == run_id)
+        .collect();
+    if entries.is_empty() {
+        anyhow::bail!("No entries found for run ID: {:?}", run_id);
+    }
+    // Along with those renamed by rehash, which are recorded again under
+    // their new names.
+    let already_undone: HashSet<PathBuf> = entries
+        .iter()
+        .filter(|entry| {
+            matches!(entry.outcome, Outcome::Undone | Outcome::Rehashed)
+        })
+        .map(|entry| entry.dst.clone())
+        .collect();
+    let manifest = manifest::Writer::open(&dst_root)?;
+    let mut report = Report::default();
+    // Most recent first, in case a later entry depends on an earlier one.
+    for entry in entries.into_iter().rev().filter(|entry| {
+        entry.outcome == Outcome::Organized
+            && !already_undone.contains(&entry.dst)
+    }) {
+        let dst = dst_root.join(&entry.dst);
+        let result = if entry.hash == hash.name() {
+            undo_entry(&dst_root, &dst, &entry, hash, reading)
+        } else {
+            match Hash::from_name(&entry.hash) {
+                Some(hash) => {
+                    undo_entry(&dst_root, &dst, &entry, &hash, reading)
+                }
+                None => {
+                    Err(anyhow::anyhow!("Unknown hash: {:?}", entry.hash))
+                }
+            }
+        };
+        match result {
+            Ok(()) => {
+                report.undone += 1;
+                let entry = manifest::Entry {
+                    time: chrono::Local::now().to_rfc3339(),
+                    outcome: Outcome::Undone,
+                    ..entry
+                };
+                manifest.append(&entry)?;
+            }
+            Err(error) => {
+                tracing::warn!(?dst, ?error, "Refusing to undo");
+                report.refused.push((dst, format!("{:#}", error)));
+            }
+        }
+    }
+    Ok(report)
+}
+
+#[tracing::instrument(level = "error", skip(dst_root, entry, hash))]
+fn undo_entry<H: Hasher>(
+    dst_root: &Path,
+    dst: &Path,
+    entry: &manifest::Entry,
+    hash: &H,
+    reading: Reading,
+) -> anyhow::Result<()> {
+    if !dst.try_exists()? {
+        anyhow::bail!("dst no longer exists");
+    }
+    files::check_digest(dst, hash, reading, &entry.digest)
+        .context("dst was modified since organized")?;
+    match entry.op {
+        Op::Show => {}
+        Op::Move => {
+            let src = entry.src.as_path();
+            if src.try_exists()? {
+                anyhow::bail!("src exists again: {:?}", src);
+            }
+            if let Some(src_parent) = src.parent() {
+                fs::create_dir_all(src_parent).context(format!(
+                    "Failed to create src parent dir: {:?}",
+                    src_parent
+                ))?;
+            }
+            tracing::info!(?src, "Moving back");
+            files::move_file(dst, src, hash, reading, &entry.digest)?;
+        }
+        Op::Copy | Op::Link | Op::Reflink => {
+            tracing::info!("Removing");
+            fs::remove_file(dst)
+                .context(format!("Failed to remove dst: {:?}", dst))?;
+        }
+    }
+    remove_empty_parents(dst_root, dst);
+    Ok(())
+}
+
+/// Remove the directories left empty, up to, but excluding, dst_root.
+fn remove_empty_parents(dst_root: &Path, path: &Path) {
+    for dir in path.ancestors().skip(1) {
+        if dir == dst_root || !dir.starts_with(dst_root) {
+            break;
+        }
+        if fs::remove_dir(dir).is_err() {
+            break;
+        }
+    }
+}
@@ -6,6 +6,7 @@ use std::{
 };
 
 use assert_cmd::{assert::OutputAssertExt, cargo::CommandCargoExt};
+use phorg::hash::{Digester, Hasher};
 use tempfile::tempdir;
 
 #[test]
@@ -19,7 +20,7 @@ fn from_empty_dst() {
     assert!(file_paths_sorted(dst).is_empty());
 
     let mut cmd = Command::cargo_bin(exe).unwrap();
-    cmd.arg(&src).arg(&dst).arg("copy");
+    cmd.arg(&src).arg(dst).arg("copy");
     cmd.assert().success();
 
     let foo_src = "foo.jpg";
@@ -57,23 +58,471 @@ fn from_empty_dst() {
     );
     assert_eq!(
         &vec![&foo_dst_path, &bar_dst_path, &baz_dst_path][..],
-        &file_paths_sorted(&dst).iter().collect::<Vec<&PathBuf>>()
+        &file_paths_sorted(dst).iter().collect::<Vec<&PathBuf>>()
     );
     assert!(files_eq(foo_src_path, foo_dst_path).unwrap());
     assert!(files_eq(bar_src_path, bar_dst_path).unwrap());
 }
 
+#[cfg(unix)]
+#[test]
+fn link() {
+    use std::os::unix::fs::MetadataExt;
+
+    phorg::tracing_init_tests(tracing::Level::DEBUG);
+
+    let exe = env!("CARGO_PKG_NAME");
+    let src = PathBuf::from("tests/data/src");
+    let dst = tempdir().unwrap();
+    let dst = dst.path();
+
+    let mut cmd = Command::cargo_bin(exe).unwrap();
+    cmd.arg("--no-exiftool").arg(&src).arg(dst).arg("link");
+    cmd.assert().success();
+
+    let foo_src_path = src.join("foo.jpg");
+    let foo_dst_path = dst.join(format!(
+        "img/2000/12/27/2000-12-27--06:47:01--{}.jpg",
+        hash(&foo_src_path)
+    ));
+    assert_eq!(
+        fs::metadata(&foo_src_path).unwrap().ino(),
+        fs::metadata(&foo_dst_path).unwrap().ino()
+    );
+}
+
+#[test]
+fn verify() {
+    phorg::tracing_init_tests(tracing::Level::DEBUG);
+
+    let exe = env!("CARGO_PKG_NAME");
+    let src = PathBuf::from("tests/data/src");
+    let dst = tempdir().unwrap();
+    let dst = dst.path();
+
+    let mut cmd = Command::cargo_bin(exe).unwrap();
+    cmd.arg("organize")
+        .arg("--no-exiftool")
+        .arg(&src)
+        .arg(dst)
+        .arg("copy");
+    cmd.assert().success();
+
+    let mut cmd = Command::cargo_bin(exe).unwrap();
+    cmd.arg("verify").arg(dst);
+    cmd.assert().success();
+
+    let foo_dst_path = dst.join(format!(
+        "img/2000/12/27/2000-12-27--06:47:01--{}.jpg",
+        hash(&src.join("foo.jpg"))
+    ));
+    let mut data = fs::read(&foo_dst_path).unwrap();
+    data.pop();
+    fs::write(&foo_dst_path, data).unwrap();
+    fs::write(dst.join("img/notes.txt"), "").unwrap();
+
+    let mut cmd = Command::cargo_bin(exe).unwrap();
+    cmd.arg("verify").arg(dst);
+    cmd.assert()
+        .code(2)
+        .stdout(predicates::str::contains("corrupted"))
+        .stdout(predicates::str::contains("unrecognized"));
+}
+
+#[test]
+fn index() {
+    phorg::tracing_init_tests(tracing::Level::DEBUG);
+
+    let exe = env!("CARGO_PKG_NAME");
+    let src = PathBuf::from("tests/data/src");
+    let dst = tempdir().unwrap();
+    let dst = dst.path();
+
+    let organize = |expected_summary: &str| {
+        let mut cmd = Command::cargo_bin(exe).unwrap();
+        cmd.arg("organize")
+            .arg("--no-exiftool")
+            .arg(&src)
+            .arg(dst)
+            .arg("copy");
+        cmd.assert()
+            .success()
+            .stderr(predicates::str::contains(expected_summary));
+    };
+
+    organize("2 organized");
+    let foo_dst_name =
+        format!("2000-12-27--06:47:01--{}.jpg", hash(&src.join("foo.jpg")));
+    let foo_dst_dir = dst.join("img/2000/12/27");
+    let foo_dst_aux_dir = foo_dst_dir.join("Semantic subdirectory");
+    fs::create_dir(&foo_dst_aux_dir).unwrap();
+    fs::rename(
+        foo_dst_dir.join(&foo_dst_name),
+        foo_dst_aux_dir.join(&foo_dst_name),
+    )
+    .unwrap();
+    let dst_paths_before = file_paths_sorted(dst);
+
+    organize("2 already present");
+    assert_eq!(dst_paths_before, file_paths_sorted(dst));
+}
+
+#[test]
+fn unsorted() {
+    phorg::tracing_init_tests(tracing::Level::DEBUG);
+
+    let exe = env!("CARGO_PKG_NAME");
+    let src = PathBuf::from("tests/data/src");
+    let dst = tempdir().unwrap();
+    let dst = dst.path();
+
+    // Without exiftool, baz.jpg has no timestamp.
+    let mut cmd = Command::cargo_bin(exe).unwrap();
+    cmd.arg("organize")
+        .arg("--no-exiftool")
+        .arg("--unsorted-dir")
+        .arg("unsorted")
+        .arg(&src)
+        .arg(dst)
+        .arg("copy");
+    cmd.assert()
+        .success()
+        .stderr(predicates::str::contains("1 unsorted"));
+    let baz_dst =
+        dst.join(format!("unsorted/{}--baz.jpg", hash(&src.join("baz.jpg"))));
+    assert!(files_eq(&src.join("baz.jpg"), &baz_dst).unwrap());
+}
+
+#[test]
+fn failure_exit_code() {
+    phorg::tracing_init_tests(tracing::Level::DEBUG);
+
+    let exe = env!("CARGO_PKG_NAME");
+    let src = PathBuf::from("tests/data/src");
+    let dst = tempdir().unwrap();
+    let dst = dst.path();
+
+    // A file in place of the img dir fails every image copy, even as root,
+    // unlike an unreadable src file would.
+    fs::write(dst.join("img"), b"").unwrap();
+    let mut cmd = Command::cargo_bin(exe).unwrap();
+    cmd.arg("organize")
+        .arg("--no-exiftool")
+        .arg(&src)
+        .arg(dst)
+        .arg("copy");
+    let output = cmd.output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(Some(2), output.status.code(), "{}", stderr);
+    assert!(stderr.contains("2 failed: I/O error"), "{}", stderr);
+    assert_eq!(b"", &fs::read(dst.join("img")).unwrap()[..]);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn unrecorded_exit_code() {
+    phorg::tracing_init_tests(tracing::Level::DEBUG);
+
+    let exe = env!("CARGO_PKG_NAME");
+    let src = PathBuf::from("tests/data/src");
+    let dst = tempdir().unwrap();
+    let dst = dst.path();
+
+    // Opens fine, but every write fails.
+    let manifest = phorg::manifest::path(dst);
+    fs::create_dir_all(manifest.parent().unwrap()).unwrap();
+    std::os::unix::fs::symlink("/dev/full", &manifest).unwrap();
+    let mut cmd = Command::cargo_bin(exe).unwrap();
+    cmd.arg("organize")
+        .arg("--no-exiftool")
+        .arg(&src)
+        .arg(dst)
+        .arg("copy");
+    let output = cmd.output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(Some(2), output.status.code(), "{}", stderr);
+    assert!(
+        stderr.contains("2 failed: not recorded in manifest"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn fs_time() {
+    phorg::tracing_init_tests(tracing::Level::DEBUG);
+
+    let exe = env!("CARGO_PKG_NAME");
+    let src = PathBuf::from("tests/data/src");
+    let dst = tempdir().unwrap();
+    let dst = dst.path();
+
+    // Without exiftool, baz.jpg has no timestamp in its metadata.
+    let mut cmd = Command::cargo_bin(exe).unwrap();
+    cmd.arg("organize")
+        .arg("--no-exiftool")
+        .arg("--fs-time")
+        .arg(&src)
+        .arg(dst)
+        .arg("copy");
+    cmd.assert()
+        .success()
+        .stderr(predicates::str::contains("3 organized"));
+    let entries = phorg::manifest::read(dst).unwrap();
+    let baz = entries
+        .iter()
+        .find(|entry| entry.src.ends_with("baz.jpg"))
+        .unwrap();
+    assert!(matches!(
+        baz.timestamp_source,
+        Some(
+            phorg::files::TimestampSource::Birthtime
+                | phorg::files::TimestampSource::Mtime
+        )
+    ));
+}
+
+#[test]
+fn custom_hasher() {
+    phorg::tracing_init_tests(tracing::Level::DEBUG);
+
+    /// Digest by file size, to tell it apart from any of the built-in ones.
+    #[derive(Debug)]
+    struct Size;
+
+    struct SizeDigester(u64);
+
+    impl Hasher for Size {
+        type Digester = SizeDigester;
+
+        fn name(&self) -> &str {
+            "size"
+        }
+
+        fn hex_len(&self) -> usize {
+            16
+        }
+
+        fn digester(&self) -> SizeDigester {
+            SizeDigester(0)
+        }
+    }
+
+    impl Digester for SizeDigester {
+        fn update(&mut self, data: &[u8]) {
+            self.0 += data.len() as u64;
+        }
+
+        fn finalize(self) -> String {
+            format!("{:016x}", self.0)
+        }
+    }
+
+    let src = PathBuf::from("tests/data/src");
+    let dst = tempdir().unwrap();
+    let dst = dst.path();
+    let opts = phorg::files::Options {
+        img_dir: "img".to_string(),
+        vid_dir: "vid".to_string(),
+        template: Default::default(),
+        portable_names: false,
+        unsorted_dir: None,
+        ty_filter: None,
+        force: false,
+        use_exiftool: false,
+        exiftool_exe: PathBuf::from("exiftool"),
+        exiftool_args: Vec::new(),
+        date_tags: phorg::files::DATE_TAGS.to_vec(),
+        name_time_patterns: Vec::new(),
+        use_fs_time: false,
+        zone: Default::default(),
+        assumed_zone: Default::default(),
+        time_shifts: Default::default(),
+        use_index: true,
+        show_progress: false,
+        hash: Size,
+        reading: Default::default(),
+        verify: true,
+    };
+    let organize = || {
+        phorg::files::organize(&src, dst, &phorg::files::Op::Copy, &opts)
+            .unwrap()
+    };
+
+    assert_eq!(2, organize().organized);
+    let foo_size = fs::metadata(src.join("foo.jpg")).unwrap().len();
+    let foo_dst = dst.join(format!(
+        "img/2000/12/27/2000-12-27--06:47:01--size:{:016x}.jpg",
+        foo_size
+    ));
+    assert!(files_eq(&src.join("foo.jpg"), &foo_dst).unwrap());
+
+    // Indexed by its digests too.
+    assert_eq!(2, organize().present);
+
+    // Undoable by it after a rehash to it.
+    let dst = tempdir().unwrap();
+    let dst = dst.path();
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.arg("--no-exiftool").arg(&src).arg(dst).arg("copy");
+    let output = cmd.output().unwrap();
+    assert!(output.status.success());
+    let run_id = String::from_utf8_lossy(&output.stderr)
+        .lines()
+        .find_map(|line| line.strip_prefix("Run ID: ").map(str::to_string))
+        .unwrap();
+    let reading = Default::default();
+    let report =
+        phorg::rehash::rehash(dst, &Size, reading, false, false).unwrap();
+    assert_eq!(2, report.renamed.len());
+    let foo_dst = dst.join(format!(
+        "img/2000/12/27/2000-12-27--06:47:01--size:{:016x}.jpg",
+        foo_size
+    ));
+    assert!(files_eq(&src.join("foo.jpg"), &foo_dst).unwrap());
+    let report = phorg::undo::undo(dst, &run_id, &Size, reading).unwrap();
+    assert_eq!((2, 0), (report.undone, report.refused.len()));
+    assert!(file_paths_sorted(&dst.join("img")).is_empty());
+}
+
+#[test]
+fn undo() {
+    phorg::tracing_init_tests(tracing::Level::DEBUG);
+
+    let exe = env!("CARGO_PKG_NAME");
+    let data = PathBuf::from("tests/data/src");
+    let src = tempdir().unwrap();
+    let src = src.path();
+    let dst = tempdir().unwrap();
+    let dst = dst.path();
+    for name in ["foo.jpg", "bar.jpg"] {
+        fs::copy(data.join(name), src.join(name)).unwrap();
+    }
+    let src_paths_before = file_paths_sorted(src);
+
+    let organize = |op: &str| -> String {
+        let mut cmd = Command::cargo_bin(exe).unwrap();
+        cmd.arg("organize")
+            .arg("--no-exiftool")
+            .arg(src)
+            .arg(dst)
+            .arg(op);
+        let out = cmd.assert().success().get_output().stderr.clone();
+        String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .find_map(|line| line.strip_prefix("Run ID: "))
+            .unwrap()
+            .to_string()
+    };
+
+    // Undo move:
+    let run_id = organize("move");
+    assert!(file_paths_sorted(src).is_empty());
+    assert_eq!(2, file_paths_sorted(dst).len());
+    let mut cmd = Command::cargo_bin(exe).unwrap();
+    cmd.arg("undo").arg(dst).arg(&run_id);
+    cmd.assert().success();
+    assert_eq!(src_paths_before, file_paths_sorted(src));
+    assert!(file_paths_sorted(dst).is_empty());
+
+    // Refuse to undo modified:
+    let run_id = organize("copy");
+    let dst_paths = file_paths_sorted(dst);
+    assert_eq!(2, dst_paths.len());
+    fs::write(&dst_paths[0], "modified").unwrap();
+    let mut cmd = Command::cargo_bin(exe).unwrap();
+    cmd.arg("undo").arg(dst).arg(&run_id);
+    cmd.assert().code(2);
+    assert_eq!(vec![dst_paths[0].clone()], file_paths_sorted(dst));
+    assert_eq!(src_paths_before, file_paths_sorted(src));
+}
+
+#[test]
+fn rehash() {
+    phorg::tracing_init_tests(tracing::Level::DEBUG);
+
+    let exe = env!("CARGO_PKG_NAME");
+    let src = PathBuf::from("tests/data/src");
+    let dst = tempdir().unwrap();
+    let dst = dst.path();
+
+    let mut cmd = Command::cargo_bin(exe).unwrap();
+    cmd.arg("organize")
+        .arg("--no-exiftool")
+        .arg(&src)
+        .arg(dst)
+        .arg("copy");
+    let output = cmd.output().unwrap();
+    assert!(output.status.success());
+    let run_id = String::from_utf8_lossy(&output.stderr)
+        .lines()
+        .find_map(|line| line.strip_prefix("Run ID: ").map(str::to_string))
+        .unwrap();
+    let foo_dst_dir = dst.join("img/2000/12/27");
+    let foo_dst_aux_dir = foo_dst_dir.join("Semantic subdirectory");
+    let foo_dst_name =
+        format!("2000-12-27--06:47:01--{}.jpg", hash(&src.join("foo.jpg")));
+    fs::create_dir(&foo_dst_aux_dir).unwrap();
+    fs::rename(
+        foo_dst_dir.join(&foo_dst_name),
+        foo_dst_aux_dir.join(&foo_dst_name),
+    )
+    .unwrap();
+    let dst_paths_before = file_paths_sorted(dst);
+
+    let rehash = |dry_run: bool, expected_summary: &str| {
+        let mut cmd = Command::cargo_bin(exe).unwrap();
+        cmd.arg("rehash").arg("--hash").arg("sha256").arg(dst);
+        if dry_run {
+            cmd.arg("--dry-run");
+        }
+        cmd.assert()
+            .success()
+            .stderr(predicates::str::contains(expected_summary));
+    };
+
+    rehash(true, "2 to rename, 0 current");
+    assert_eq!(dst_paths_before, file_paths_sorted(dst));
+
+    rehash(false, "2 renamed, 0 current");
+    let foo_sha256 = phorg::hash::Hash::Sha256
+        .digest(&src.join("foo.jpg"), Default::default())
+        .unwrap();
+    let foo_dst = foo_dst_aux_dir
+        .join(format!("2000-12-27--06:47:01--sha256:{}.jpg", foo_sha256));
+    assert!(files_eq(&src.join("foo.jpg"), &foo_dst).unwrap());
+
+    rehash(false, "0 renamed, 2 current");
+
+    let mut cmd = Command::cargo_bin(exe).unwrap();
+    cmd.arg("verify").arg(dst);
+    cmd.assert().success();
+
+    // Still undoable under the new names, except for foo, which was moved
+    // since organized.
+    let mut cmd = Command::cargo_bin(exe).unwrap();
+    cmd.arg("undo").arg(dst).arg(&run_id);
+    cmd.assert()
+        .code(2)
+        .stderr(predicates::str::contains("1 undone, 1 refused"));
+    assert_eq!(vec![foo_dst], file_paths_sorted(dst));
+}
+
 fn hash(path: &Path) -> String {
     format!(
         "{}:{}",
         phorg::hash::Hash::Crc32.name(),
-        phorg::hash::Hash::Crc32.digest(path).unwrap()
+        phorg::hash::Hash::Crc32
+            .digest(path, Default::default())
+            .unwrap()
     )
 }
 
 fn file_paths_sorted(root: &Path) -> Vec<PathBuf> {
-    let mut paths: Vec<PathBuf> =
-        phorg::files::FilePaths::find(&root).collect();
+    let mut paths: Vec<PathBuf> = phorg::files::FilePaths::find(root)
+        .filter(|path| !phorg::manifest::is_internal(root, path))
+        .collect();
     paths.sort();
     paths
 }